@@ -1,20 +1,208 @@
-use pleco::{Board, Player, Piece, BitMove, SQ, MoveList};
+use pleco::{Board, Player, Piece, PieceType, BitMove, BitBoard, SQ, MoveList};
+use pleco::core::GenTypes;
+use rand::Rng;
 use std::{io,time::Instant, f32};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
 
 const MINIMUM_EVAL: i32 = -2_147_483_647;
 const MAXIMUM_EVAL: i32 = 2_147_483_647;
 const MAX_EXTENSIONS: u8 = 8;
+// defaults and maxima of the `FutilityDepth` and `FutilityMargin` options
+const DEFAULT_FUTILITY_DEPTH: u8 = 3;
+const MAX_FUTILITY_DEPTH: u8 = 8;
+const DEFAULT_FUTILITY_MARGIN: i32 = 300;
+const MAX_FUTILITY_MARGIN: i32 = 2000;
+// `FutilityMinPhase`: below this game phase nothing is futility pruned. 3 keeps pawn endings
+// and those with one or two minor pieces out, where zugzwang and quiet pawn moves decide
+const DEFAULT_FUTILITY_MIN_PHASE: i32 = 3;
+// stands for the position after a null move in `position_keys`, no real key is zero
+const NULL_MOVE_KEY: u64 = 0;
+// at this depth and below, captures losing more than the margin per ply of depth are skipped
+const SEE_PRUNE_DEPTH: u8 = 3;
+const SEE_PRUNE_MARGIN: i32 = 100;
 const TRANSPOSITION_OBJECT_BYTES: usize = 16;
 const MB_TO_ITEMS: usize = 1024 * 1024 / TRANSPOSITION_OBJECT_BYTES;
+// a position may sit in either slot of its two-slot bucket
+const BUCKET_SLOTS: usize = 2;
+// generations wrap at 64, they are stored in 6 bits
+const GENERATION_MASK: u8 = 63;
+// default of `HashAgeLimit`, searches after which an entry is stale and the first to be replaced
+const DEFAULT_HASH_AGE_LIMIT: u8 = 4;
+// hashfull (permille) above which a store may only evict a deeper entry of the current search if it is as deep
+const HASH_PRESSURE_HASHFULL: usize = 900;
+// the most of the available memory a new transposition table may take
+const HASH_MAX_MEMORY_PERCENT: usize = 50;
+// `Hash` range in MB, as advertised to the GUI
+const MIN_HASH_MB: usize = 1;
+const MAX_HASH_MB: usize = 4096;
+const HASH_DUMP_MAGIC: &[u8; 8] = b"TBOTHASH";
+const HASH_DUMP_VERSION: u32 = 2;
+const NNUE_MAGIC: &[u8; 8] = b"TBOTNNUE";
+// one input per (colour, piece type, square)
+const NNUE_INPUTS: usize = 768;
+// hidden activations are clipped to [0, NNUE_CLIP] and the output divided by NNUE_SCALE for cp
+const NNUE_CLIP: i32 = 127;
+const NNUE_SCALE: i32 = 64;
+const NNUE_MAX_HIDDEN: usize = 4096;
+// how a stored score relates to the true value of the position
+const BOUND_EXACT: u8 = 0;
+const BOUND_LOWER: u8 = 1;
+const BOUND_UPPER: u8 = 2;
+const HISTORY_MAX: i32 = 16_384;
+// history score worth one ply of extra (or less) reduction
+const HISTORY_REDUCTION_DIVISOR: f32 = 8_192.0;
+const LMR_MIN_DEPTH: u8 = 4;
+const LMR_MIN_MOVE_INDEX: usize = 3;
+// plies less reduction for the late moves of a PV node
+const PV_REDUCTION_CUT: f32 = 0.5;
+// defaults of the `LMRBase` and `LMRDivisor` options
+const LMR_BASE: f32 = 0.75;
+const LMR_DIVISOR: f32 = 2.25;
+const LMR_MIN_DIVISOR: f32 = 0.1;
+// rows of the reduction table are depths, columns move indices, more than any position has moves
+const LMR_TABLE_SIZE: usize = 256;
+// indexed by PieceType: none, pawn, knight, bishop, rook, queen, king
+const DEFAULT_PIECE_VALUES: [i32; 7] = [0, 100, 320, 330, 500, 900, 0];
+// every table indexed by PieceType relies on pleco's numbering, a release that changes it fails to build here
+const _: () = assert!(PieceType::P as usize == 1 && PieceType::N as usize == 2 && PieceType::B as usize == 3
+    && PieceType::R as usize == 4 && PieceType::Q as usize == 5 && PieceType::K as usize == 6);
+// minimum non-pawn material lead (cp) before the mop-up term kicks in
+const MOP_UP_MIN_ADVANTAGE: i32 = 400;
+const MOP_UP_EDGE_WEIGHT: i32 = 10;
+const MOP_UP_PROXIMITY_WEIGHT: i32 = 4;
+const DOUBLED_PAWN_PENALTY: i32 = 15;
+const ISOLATED_PAWN_PENALTY: i32 = 10;
+// indexed by the rank of a passed pawn counted from its own side
+const PASSED_PAWN_BONUS: [i32; 8] = [0, 5, 10, 20, 35, 60, 100, 0];
+const PAWN_HASH_ENTRIES: usize = 4096;
+// `EvalCache`, MB per search thread
+const DEFAULT_EVAL_CACHE_MB: usize = 1;
+const MAX_EVAL_CACHE_MB: usize = 256;
+// game phase from the non-pawn material left, indexed by PieceType. all of it is MAX_PHASE
+const PHASE_WEIGHTS: [i32; 7] = [0, 0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+// at or below this phase the endgame tables and terms are used
+const ENDGAME_PHASE: i32 = 8;
+// with `ContemptDynamic`, a draw offset against us is gone once we are this much material down
+const CONTEMPT_DYNAMIC_RANGE: i32 = 500;
+const MAX_DRAW_SCORE: i32 = 1000;
+// `UCI_ShowWDL` model: the win chance is a logistic curve of (cp - margin) / scale, the loss chance the same
+// for the other side, and whatever is left over is the draw
+const WDL_DRAW_MARGIN: f64 = 100.0;
+const WDL_SCALE: f64 = 60.0;
+// `tune`: eval scaling of the texel sigmoid, the piece value step and how many steps one value may move
+const TUNE_K: f64 = 1.0;
+const TUNE_STEP: i32 = 5;
+const TUNE_MAX_STEPS: usize = 20;
+// from this many plies without a capture or pawn move on, scores shrink toward the draw score,
+// reaching it at the fifty-move limit. a winning side then prefers the moves that reset the clock
+const FIFTY_MOVE_SCALE_START: i32 = 60;
+const FIFTY_MOVE_LIMIT: i32 = 100;
+// endgame only, per square of king distance to a passed pawn, times the pawn's rank from its own side
+const PASSED_PAWN_KING_WEIGHT: i32 = 4;
+// pawn endgames only, a passed pawn the enemy king can't catch before it queens
+const UNSTOPPABLE_PAWN_BONUS: i32 = 600;
+// per safe square a piece attacks, indexed by PieceType
+const MOBILITY_WEIGHTS: [i32; 7] = [0, 0, 4, 3, 2, 1, 0];
+// knight or bishop in the enemy half, defended by a pawn and out of reach of the enemy pawns
+const OUTPOST_BONUS: i32 = 20;
+const PAWN_SHIELD_BONUS: i32 = 10;
+// per square of the enemy king zone a piece attacks, indexed by PieceType
+const KING_ATTACK_WEIGHTS: [i32; 7] = [0, 0, 6, 6, 8, 10, 0];
+// per square a piece is closer to the enemy king than the far side of the board, indexed by PieceType.
+// scaled down with the game phase
+const KING_TROPISM_WEIGHTS: [i32; 7] = [0, 0, 2, 1, 2, 4, 0];
+// time management (ms)
+const DEFAULT_MOVETIME: u32 = 8000;
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// lag between sending bestmove and the GUI stopping our clock, set with `Move Overhead`
+const DEFAULT_MOVE_OVERHEAD: u32 = 50;
+const MAX_MOVE_OVERHEAD: u32 = 5000;
+const MAX_NODES_TIME: u64 = 10000;
+const DEFAULT_VALIDATE_MOVES: usize = 100;
+// half-width (cp) of the first root window around the previous iteration's score, 0 for full windows
+const DEFAULT_ASPIRATION_WINDOW: i32 = 25;
+const MAX_ASPIRATION_WINDOW: i32 = 1000;
+// how far past the target an unstable search may run
+const MAXIMUM_TIME_FACTOR: u32 = 3;
+// a root score drop of this many cp between iterations stretches the target time
+const PANIC_SCORE_DROP: i32 = 50;
+const PANIC_TIME_FACTOR: u32 = 2;
+// with `StableDraw`, an endgame whose score stayed this close to the draw score for this many
+// iterations in a row, the last at least this deep, is taken as a draw and the search stops there
+const STABLE_DRAW_BAND: i32 = 10;
+const STABLE_DRAW_ITERATIONS: u32 = 4;
+const STABLE_DRAW_MIN_DEPTH: u8 = 8;
+// the node ceiling allows this many times the nodes the measured nps fits in the remaining time
+const NODE_LIMIT_FACTOR: u128 = 3;
+const OPENING_VARIETY_MAX: i32 = 20;
+const OPENING_VARIETY_PLIES: u16 = 8;
+// root scores beyond this are decisive and the best move is always played
+const OPENING_VARIETY_SCORE_LIMIT: i32 = 150;
+const MAX_THREADS: usize = 64;
+const DEFAULT_MAX_DEPTH: u8 = 20;
+const MAX_DEPTH_LIMIT: u8 = 100;
+// no line goes further from the root than this, extensions and quiescence included. a ply takes
+// about 2 KB of stack (3 KB unoptimized), so this stays well inside the 2 MB of a spawned thread
+const MAX_SEARCH_PLY: u16 = 256;
+const MAX_MULTIPV: usize = 256;
+const MAX_SKILL_LEVEL: u8 = 20;
+// below the maximum skill level the move is picked from this many root lines
+const SKILL_LINES: usize = 4;
+// cp of random noise per level below the maximum, added to the line scores before picking
+const SKILL_NOISE_PER_LEVEL: i32 = 15;
+// the words `go` takes a value (or nothing) after, anything else following `searchmoves` is a move
+const NUMERIC_GO_PARAMETERS: [&str; 10] = ["wtime", "btime", "winc", "binc", "movestogo", "depth", "nodes", "mate", "movetime", "maxmoves"];
+const GO_PARAMETERS: [&str; 13] = ["searchmoves", "ponder", "wtime", "btime", "winc", "binc", "movestogo", "depth", "nodes", "mate", "movetime", "infinite", "maxmoves"];
 
 
+// pleco 0.5 never seeds the zobrist keys of the black king, so positions that only differ
+// in its square share a hash; these keys are mixed in by `position_key`
+const BLACK_KING_KEYS: [u64; 64] = black_king_keys();
+
+const fn black_king_keys() -> [u64; 64] {
+    let mut keys = [0; 64];
+    let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    let mut i = 0;
+    while i < 64 {
+        // splitmix64
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+}
+
+// MemAvailable in bytes, None where /proc/meminfo doesn't exist
+fn available_memory() -> Option<usize> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemAvailable:"))?;
+    let kilobytes: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+fn position_key(board:&Board) -> u64 {
+    board.zobrist() ^ BLACK_KING_KEYS[board.king_sq(Player::Black).0 as usize]
+}
 
 #[derive(Clone, Copy)]
-// Each TranspositionObject is 16 bytes
+// Each TranspositionObject is 16 bytes, stored in the table as two words
 struct TranspositionObject {
     hash: u64,
     score: i32,
     depth: u8,
+    bound: u8,
+    // the search that stored it, see `Engine::generation`
+    generation: u8,
     best_move: BitMove,
 }
 
@@ -24,681 +212,5113 @@ impl TranspositionObject {
             hash: 0,
             score: 0,
             depth: 0,
+            bound: BOUND_EXACT,
+            generation: 0,
             best_move: BitMove::null(),
         }
     }
+
+    // everything but the hash in one word: score, depth, bound and generation sharing a byte, move
+    fn data(&self) -> u64 {
+        self.score as u32 as u64
+            | (self.depth as u64) << 32
+            | (self.bound as u64) << 40
+            | (self.generation as u64) << 42
+            | (self.best_move.get_raw() as u64) << 48
+    }
+
+    fn from_data(hash:u64, data:u64) -> TranspositionObject {
+        TranspositionObject {
+            hash,
+            score: data as u32 as i32,
+            depth: (data >> 32) as u8,
+            bound: (data >> 40) as u8 & 3,
+            generation: (data >> 42) as u8 & GENERATION_MASK,
+            best_move: BitMove::new((data >> 48) as u16),
+        }
+    }
+}
+
+// Shared by all search threads without locking. A slot holds (hash ^ data, data), so a slot
+// torn by two threads writing at once fails the hash check instead of returning a mixed entry
+struct TranspositionTable {
+    slots: Vec<[AtomicU64; 2]>,
+    filled: AtomicUsize,
+}
+
+impl TranspositionTable {
+    fn new(size_in_mb:usize) -> TranspositionTable {
+        TranspositionTable {
+            slots: (0..size_in_mb * MB_TO_ITEMS).map(|_| Default::default()).collect(),
+            filled: AtomicUsize::new(0),
+        }
+    }
+
+    fn load(&self, index:usize) -> TranspositionObject {
+        let check = self.slots[index][0].load(Ordering::Relaxed);
+        let data = self.slots[index][1].load(Ordering::Relaxed);
+        TranspositionObject::from_data(check ^ data, data)
+    }
+
+    fn store(&self, index:usize, transpos_object:TranspositionObject) {
+        let data = transpos_object.data();
+        self.slots[index][0].store(transpos_object.hash ^ data, Ordering::Relaxed);
+        self.slots[index][1].store(data, Ordering::Relaxed);
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// The `Threads` - 1 search threads besides the main one. Started when the option is set and
+// parked on their channel between searches, so a `go` doesn't pay for creating threads
+struct ThreadPool {
+    jobs: Vec<mpsc::Sender<Job>>,
+    // one message per finished job, false if it panicked
+    finished: Mutex<mpsc::Receiver<bool>>,
+    thread_ids: Vec<thread::ThreadId>,
+}
+
+impl ThreadPool {
+    fn new(size:usize) -> ThreadPool {
+        let (finished_sender, finished) = mpsc::channel();
+        let mut jobs = Vec::new();
+        let mut thread_ids = Vec::new();
+        for _ in 0..size {
+            let (job_sender, job_receiver) = mpsc::channel::<Job>();
+            let finished_sender = finished_sender.clone();
+            // ends when the pool is dropped and the channel with it
+            let handle = thread::spawn(move || {
+                for job in job_receiver {
+                    let completed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job)).is_ok();
+                    let _ = finished_sender.send(completed);
+                }
+            });
+            jobs.push(job_sender);
+            thread_ids.push(handle.thread().id());
+        }
+        ThreadPool { jobs, finished: Mutex::new(finished), thread_ids }
+    }
+
+    // one job per thread, returns how many `wait` has to wait for
+    fn start(&self, jobs:Vec<Job>) -> usize {
+        assert!(jobs.len() <= self.jobs.len(), "more search jobs than pool threads");
+        let count = jobs.len();
+        for (sender, job) in self.jobs.iter().zip(jobs) {
+            sender.send(job).expect("search thread exited");
+        }
+        count
+    }
+
+    fn wait(&self, count:usize) {
+        let finished = self.finished.lock().unwrap();
+        for _ in 0..count {
+            assert!(finished.recv().unwrap_or(false), "search thread panicked");
+        }
+    }
+}
+
+// bitboards several evaluation terms need, computed once per `evaluate`. indexed by player
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct EvalInfo {
+    pawn_attacks: [BitBoard; 2],
+    // every square the pawns attack now or could attack after advancing
+    pawn_attack_spans: [BitBoard; 2],
+    // the king and the squares around it
+    king_zones: [BitBoard; 2],
+}
+
+impl EvalInfo {
+    // everything from scratch, `Engine::eval_info` takes the pawn bitboards from the pawn hash instead.
+    // only the tests check the hashed ones against it
+    #[cfg(test)]
+    fn new(board:&Board) -> EvalInfo {
+        let (pawn_attacks, pawn_attack_spans) = pawn_attacks_and_spans(board);
+        EvalInfo::with_pawns(board, pawn_attacks, pawn_attack_spans)
+    }
+
+    fn with_pawns(board:&Board, pawn_attacks:[BitBoard; 2], pawn_attack_spans:[BitBoard; 2]) -> EvalInfo {
+        let mut king_zones = [BitBoard(0); 2];
+        for player in [Player::White, Player::Black] {
+            let king = board.king_sq(player);
+            king_zones[player as usize] = board.attacks_from(PieceType::K, king, player) | king.to_bb();
+        }
+
+        EvalInfo { pawn_attacks, pawn_attack_spans, king_zones }
+    }
+}
+
+// (pawn attacks, pawn attack spans) indexed by player, they depend on the pawns alone
+fn pawn_attacks_and_spans(board:&Board) -> ([BitBoard; 2], [BitBoard; 2]) {
+    let mut pawn_attacks = [BitBoard(0); 2];
+    let mut pawn_attack_spans = [BitBoard(0); 2];
+
+    for player in [Player::White, Player::Black] {
+        let side = player as usize;
+
+        for square in board.piece_bb(player, PieceType::P) {
+            pawn_attacks[side] |= board.attacks_from(PieceType::P, square, player);
+        }
+
+        let mut span = pawn_attacks[side];
+        for _ in 0..6 {
+            span |= if player == Player::White { span << 8 } else { span >> 8 };
+        }
+        pawn_attack_spans[side] = span;
+    }
+
+    (pawn_attacks, pawn_attack_spans)
+}
+
+// `EvalFile` network: 768 piece-square inputs, one hidden layer, a single output from white's point of view.
+// file layout, little endian: magic, u32 hidden size, i16 input weights [input][hidden], i16 hidden biases,
+// i16 output weights, i32 output bias
+struct Network {
+    hidden: usize,
+    input_weights: Vec<i16>,
+    hidden_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network {
+    fn load(path:&str) -> io::Result<Network> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8; 8];
+        let mut hidden = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        reader.read_exact(&mut hidden)?;
+
+        if &magic != NNUE_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a network file"));
+        }
+        let hidden = u32::from_le_bytes(hidden) as usize;
+        if hidden == 0 || hidden > NNUE_MAX_HIDDEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported hidden layer size {}", hidden)));
+        }
+
+        let mut read_i16s = |count:usize| -> io::Result<Vec<i16>> {
+            let mut bytes = vec![0u8; count * 2];
+            reader.read_exact(&mut bytes)?;
+            Ok(bytes.chunks_exact(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])).collect())
+        };
+        let input_weights = read_i16s(NNUE_INPUTS * hidden)?;
+        let hidden_biases = read_i16s(hidden)?;
+        let output_weights = read_i16s(hidden)?;
+
+        let mut output_bias = [0u8; 4];
+        reader.read_exact(&mut output_bias)?;
+
+        Ok(Network { hidden, input_weights, hidden_biases, output_weights, output_bias: i32::from_le_bytes(output_bias) })
+    }
+
+    fn feature(piece:Piece, square:SQ) -> usize {
+        let colour = { if piece.player() == Some(Player::White) { 0 } else { 1 } };
+        (colour * 6 + piece.type_of() as usize - 1) * 64 + square.0 as usize
+    }
+
+    // adds (sign 1) or removes (sign -1) one piece from an accumulator
+    fn update(&self, accumulator:&mut [i32], piece:Piece, square:SQ, sign:i32) {
+        let weights = &self.input_weights[Network::feature(piece, square) * self.hidden..][..self.hidden];
+        for (value, &weight) in accumulator.iter_mut().zip(weights) {
+            *value += sign * weight as i32;
+        }
+    }
+
+    fn refresh(&self, board:&Board) -> Vec<i32> {
+        let mut accumulator: Vec<i32> = self.hidden_biases.iter().map(|&bias| bias as i32).collect();
+        for location in 0..64 {
+            let piece = board.piece_at_sq(SQ(location));
+            if piece != Piece::None {
+                self.update(&mut accumulator, piece, SQ(location), 1);
+            }
+        }
+        accumulator
+    }
+
+    fn output(&self, accumulator:&[i32]) -> i32 {
+        let sum: i32 = accumulator.iter().zip(&self.output_weights)
+            .map(|(&value, &weight)| value.clamp(0, NNUE_CLIP) * weight as i32)
+            .sum();
+        (sum + self.output_bias) / NNUE_SCALE
+    }
+}
+
+// piece values indexed by PieceType and the middlegame/endgame piece-square tables, as `EvalFile` loads them
+type EvalWeights = ([i32; 7], [[[i32; 64]; 7]; 2]);
+
+// the text form of `EvalFile`: whitespace separated integers, `#` starts a comment. first the
+// 7 piece values indexed by PieceType, then the 2 x 7 tables of 64 squares in PIECE_TABLES_ALL order
+fn load_eval_weights(text:&str) -> Result<EvalWeights, String> {
+    let mut numbers = Vec::new();
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        for word in line.split_whitespace() {
+            let number = word.parse::<i32>().map_err(|_| format!("line {}: '{}' is not a number", line_index + 1, word))?;
+            numbers.push(number);
+        }
+    }
+
+    let expected = 7 + 2 * 7 * 64;
+    if numbers.len() != expected {
+        return Err(format!("expected 7 piece values and 2 x 7 x 64 table entries ({} numbers), found {}", expected, numbers.len()));
+    }
+
+    let mut piece_values = [0; 7];
+    piece_values.copy_from_slice(&numbers[..7]);
+
+    let mut piece_tables = [[[0; 64]; 7]; 2];
+    for (index, chunk) in numbers[7..].chunks_exact(64).enumerate() {
+        piece_tables[index / 7][index % 7].copy_from_slice(chunk);
+    }
+
+    Ok((piece_values, piece_tables))
+}
+
+// `Evaluation` option: material and piece-square tables only, or with the positional terms on top
+#[derive(Clone, Copy, PartialEq)]
+enum EvaluationMode {
+    Classic,
+    Full,
+}
+
+// `Thread Mode` option: helpers racing through the whole tree on a shared table, or the root
+// moves dealt out to the threads one by one
+#[derive(Clone, Copy, PartialEq)]
+enum ThreadMode {
+    LazySmp,
+    RootSplit,
+}
+
+// `CaptureExtension` option: which captures are searched a ply deeper
+#[derive(Clone, Copy, PartialEq)]
+enum CaptureExtension {
+    All,
+    // only taking back on the square the previous move captured on
+    Recaptures,
+    Off,
 }
 
+// a `testsuite` position with its EPD `bm` and `am` moves and `id`
+struct TestPosition {
+    board: Board,
+    id: String,
+    best_moves: Vec<BitMove>,
+    avoid_moves: Vec<BitMove>,
+}
+
+#[derive(Clone, Copy)]
+struct PawnHashEntry {
+    key: u64,
+    score: i32,
+    // EvalInfo's pawn bitboards, keyed on the pawns just like the score
+    pawn_attacks: [BitBoard; 2],
+    pawn_attack_spans: [BitBoard; 2],
+}
+
+#[derive(Clone, Copy)]
+struct EvalCacheEntry {
+    key: u64,
+    score: i32,
+}
+
+#[derive(Clone)]
 struct Engine {
     board: Board,
-    search_stopped: bool,
+    // shared with the helper threads, which stop as soon as it is set
+    search_stopped: Arc<AtomicBool>,
+    // set by the main thread once its own search is over, ends the helpers
+    search_finished: Arc<AtomicBool>,
+    // set from `go ponder` until `ponderhit`, time limits are ignored meanwhile.
+    // both flags are flipped by the input thread the moment the command is read
+    pondering: Arc<AtomicBool>,
+    // from the input thread reading `go` until that search has answered, `isready` meanwhile is
+    // answered by the input thread itself
+    searching: Arc<AtomicBool>,
+    // while the main thread searches depth 1, which stops and limits don't cut short, so every
+    // search has at least one finished iteration to answer with
+    first_iteration: bool,
+    // UCI `Ponder` option, the GUI telling us it may send `go ponder`
+    ponder: bool,
     active: bool,
     wtime: u32,
     btime: u32,
+    winc: u32,
+    binc: u32,
+    movestogo: u32,
+    move_overhead: u32,
+    // `NodesTime`: nodes that count as one millisecond, 0 to use the real clock
+    nodes_time: u64,
+    // hard limit checked inside the search
     movetime: u32,
+    // no new iteration is started past this point unless the best move just changed
+    target_time: u32,
+    // backstop for the hard limit, in nodes of this thread, re-derived after every iteration
+    node_limit: u64,
+    // `go nodes`, total nodes of all threads, 0 for no limit
+    max_nodes: u64,
+    // root moves allowed by `go searchmoves`, empty for all of them
+    search_moves: Vec<BitMove>,
+    // principal variation of the last search and the key of the position it starts from,
+    // its second move is the reply we ponder on
+    pv: Vec<BitMove>,
+    pv_root_key: u64,
+    // score of that line from the side to move's point of view, for `why`
+    pv_score: i32,
+    // (move, score) of every MultiPV line of the last finished iteration, best first, scores from white's point of view
+    pv_lines: Vec<(BitMove, i32)>,
+    // plies from the root past which nothing is searched, 0 for no limit (`go maxmoves`)
+    max_plies: u16,
+    // the root moves the current search pass may play, empty for all of them
+    root_moves: Vec<BitMove>,
+    // root moves ordered by the last full root pass, best first, and the key of that root.
+    // a different position simply doesn't match the key
+    root_order: Vec<BitMove>,
+    root_order_key: u64,
+    multipv: usize,
+    // 0-20, anything below 20 caps the depth and picks noisy moves
+    skill_level: u8,
+    aspiration_window: i32,
+    // root searches of the current `go` that fell outside their window, from the side to move's point of view
+    aspiration_fail_highs: u32,
+    aspiration_fail_lows: u32,
+    // `FutilityDepth` and `FutilityMargin`: moves at or below this depth are pruned when
+    // margin * depth^2 on top of the static eval can't reach the bound
+    futility_depth: u8,
+    futility_margin: i32,
+    // `FutilityMinPhase`, the static margins are least reliable in the late endgame
+    futility_min_phase: i32,
+    // `CheckExtension` and `CaptureExtension`, at most MAX_EXTENSIONS along a line. both off by default
+    check_extension: bool,
+    capture_extension: CaptureExtension,
+    // reduction = lmr_base + ln(depth) * ln(move index) / lmr_divisor, precomputed into
+    // lmr_table[depth][move index] whenever either changes
+    lmr_base: f32,
+    lmr_divisor: f32,
+    lmr_table: Vec<[f32; LMR_TABLE_SIZE]>,
     depth: u8,
+    // `MaxDepth`, caps every search whatever `go depth` asked for
+    max_depth: u8,
     instant: Instant,
-    nodes: u128,
+    // one slot per search thread, each only written by its own thread.
+    // every visited node, quiescence ones included
+    nodes: Arc<Vec<AtomicU64>>,
+    qnodes: Arc<Vec<AtomicU64>>,
+    // 0 is the main thread, the rest are Lazy SMP helpers or root split workers
+    thread_index: usize,
+    threads: usize,
+    // threads - 1 parked threads, shared by every clone of the engine
+    pool: Arc<ThreadPool>,
+    thread_mode: ThreadMode,
+    debug: bool,
     hash_table_size_mb: usize,
-    transposition_table: Vec<TranspositionObject>,
-    entries_filled: u32,
+    transposition_table: Arc<TranspositionTable>,
+    // bumped by every search, entries older than hash_age_limit generations are replaced first
+    generation: u8,
+    hash_age_limit: u8,
+    // [side][from][to], rewarded on quiet beta cutoffs
+    history: [[[i32; 64]; 64]; 2],
+    piece_values: [i32; 7],
+    piece_tables: [[[i32; 64]; 7]; 2],
+    // [piece][to] of the opponent's move -> the quiet move that last refuted it
+    countermoves: [[BitMove; 64]; 16],
+    // score of a drawn position from white's point of view, not side-relative like contempt
+    draw_offset: i32,
+    // shrink a draw offset that works against the side we play as we fall behind in material
+    contempt_dynamic: bool,
+    // `DrawScore`, what a draw is worth to the side to move in the drawn position, on top of draw_offset
+    draw_value: i32,
+    // maximum cp of random noise added to root moves in the opening
+    opening_variety: i32,
+    // set by GUIs for analysis: no early exits, no draw offset, no root noise
+    analyse_mode: bool,
+    // `UCI_ShowWDL`, win/draw/loss permilles after every score
+    show_wdl: bool,
+    // `StableDraw`, stop searching endgames that keep scoring as a draw
+    stable_draw: bool,
+    // set by `solve`, the search prints no info or bestmove lines of its own
+    quiet: bool,
+    evaluation_mode: EvaluationMode,
+    // pawn structure scores keyed on pleco's pawn key, per thread
+    pawn_hash: Vec<PawnHashEntry>,
+    // classical evaluations before fifty-move scaling, keyed on the position key, per thread.
+    // empty when `EvalCache` is 0, cleared whenever an option changes
+    eval_cache: Vec<EvalCacheEntry>,
+    eval_cache_mb: usize,
+    // the current search's probes and hits, for `debug`
+    eval_cache_probes: u64,
+    eval_cache_hits: u64,
+    // `SearchLog` file, a line per completed depth and one per bestmove. written by the main thread only
+    search_log: Option<Arc<Mutex<BufWriter<File>>>>,
+    // loaded from `EvalFile`, only evaluates while `UseNNUE` is on
+    network: Option<Arc<Network>>,
+    // the `SearchLog` and `EvalFile` values as given, for `options`
+    search_log_path: String,
+    eval_file: String,
+    use_nnue: bool,
+    // (position key, accumulator) of every position `make_move` reached in the current line, per thread
+    accumulators: Vec<(u64, Vec<i32>)>,
+    // keys of the positions before the current one, the game's since the last capture or pawn move
+    // and then the search line's, oldest first, per thread
+    position_keys: Vec<u64>,
+    // `self.board`'s ply, the search's plies are counted from it
+    root_ply: u16,
+    // from `UCI_Opponent`, None when the GUI doesn't know
+    opponent_rating: Option<u32>,
+    opponent_name: String,
 }
 
 impl Engine {
 
     fn new(hash_size_in_mb:usize) -> Engine {
+        // a table without slots has no bucket to index into
+        let hash_size_in_mb = hash_size_in_mb.max(MIN_HASH_MB);
         Engine { 
             board: Board::start_pos(), 
-            search_stopped: true, 
+            search_stopped: Arc::new(AtomicBool::new(true)), 
+            search_finished: Arc::new(AtomicBool::new(false)),
+            searching: Arc::new(AtomicBool::new(false)),
+            first_iteration: false,
+            pondering: Arc::new(AtomicBool::new(false)),
+            ponder: false,
             active: true, 
             wtime: 0, 
             btime: 0, 
+            winc: 0,
+            binc: 0,
+            movestogo: 0,
+            move_overhead: DEFAULT_MOVE_OVERHEAD,
+            nodes_time: 0,
             movetime: 0, 
-            depth: 20,
+            target_time: 0,
+            node_limit: u64::MAX,
+            max_nodes: 0,
+            search_moves: Vec::new(),
+            pv: Vec::new(),
+            pv_root_key: 0,
+            pv_score: 0,
+            pv_lines: Vec::new(),
+            max_plies: 0,
+            root_moves: Vec::new(),
+            root_order: Vec::new(),
+            root_order_key: 0,
+            multipv: 1,
+            skill_level: MAX_SKILL_LEVEL,
+            aspiration_window: DEFAULT_ASPIRATION_WINDOW,
+            aspiration_fail_highs: 0,
+            aspiration_fail_lows: 0,
+            futility_depth: DEFAULT_FUTILITY_DEPTH,
+            futility_min_phase: DEFAULT_FUTILITY_MIN_PHASE,
+            futility_margin: DEFAULT_FUTILITY_MARGIN,
+            check_extension: false,
+            capture_extension: CaptureExtension::Off,
+            lmr_base: LMR_BASE,
+            lmr_divisor: LMR_DIVISOR,
+            lmr_table: lmr_table(LMR_BASE, LMR_DIVISOR),
+            depth: DEFAULT_MAX_DEPTH,
+            max_depth: DEFAULT_MAX_DEPTH,
             instant: Instant::now(),
-            nodes: 0,
+            nodes: Arc::new(vec![AtomicU64::new(0)]),
+            qnodes: Arc::new(vec![AtomicU64::new(0)]),
+            thread_index: 0,
+            threads: 1,
+            pool: Arc::new(ThreadPool::new(0)),
+            thread_mode: ThreadMode::LazySmp,
+            debug: false,
             hash_table_size_mb: hash_size_in_mb,
-            transposition_table: vec![TranspositionObject::new(); hash_size_in_mb * MB_TO_ITEMS],
-            entries_filled: 0,
+            transposition_table: Arc::new(TranspositionTable::new(hash_size_in_mb)),
+            generation: 0,
+            hash_age_limit: DEFAULT_HASH_AGE_LIMIT,
+            history: [[[0; 64]; 64]; 2],
+            piece_values: DEFAULT_PIECE_VALUES,
+            piece_tables: PIECE_TABLES_ALL,
+            countermoves: [[BitMove::null(); 64]; 16],
+            draw_offset: 0,
+            contempt_dynamic: false,
+            draw_value: 0,
+            opening_variety: 0,
+            analyse_mode: false,
+            show_wdl: false,
+            stable_draw: false,
+            quiet: false,
+            evaluation_mode: EvaluationMode::Full,
+            pawn_hash: vec![PawnHashEntry { key: 0, score: 0, pawn_attacks: [BitBoard(0); 2], pawn_attack_spans: [BitBoard(0); 2] }; PAWN_HASH_ENTRIES],
+            eval_cache: vec![EvalCacheEntry { key: 0, score: 0 }; eval_cache_entries(DEFAULT_EVAL_CACHE_MB)],
+            eval_cache_mb: DEFAULT_EVAL_CACHE_MB,
+            eval_cache_probes: 0,
+            eval_cache_hits: 0,
+            search_log: None,
+            network: None,
+            search_log_path: String::from("<empty>"),
+            eval_file: String::from("<empty>"),
+            use_nnue: false,
+            accumulators: Vec::new(),
+            position_keys: Vec::new(),
+            root_ply: 0,
+            opponent_rating: None,
+            opponent_name: String::new(),
         }
     }
 
     fn out_of_time(&self) -> bool {
-        if &self.instant.elapsed().as_millis() > &self.movetime.into() {
-            true
-        }
-        else {
-            false
+        !self.first_iteration && (self.search_stopped.load(Ordering::Relaxed)
+            || self.search_finished.load(Ordering::Relaxed)
+            || (!self.pondering.load(Ordering::Relaxed)
+                && (self.nodes[self.thread_index].load(Ordering::Relaxed) > self.node_limit
+                    || (self.max_nodes != 0 && self.total_nodes() >= self.max_nodes)
+                    || self.elapsed() > self.movetime.into())))
+    }
+
+    // milliseconds since `go`, counted in nodes searched when `NodesTime` is set
+    fn elapsed(&self) -> u128 {
+        match self.total_nodes().checked_div(self.nodes_time) {
+            Some(millis) => millis as u128,
+            None => self.instant.elapsed().as_millis(),
         }
     }
 
     fn re_initialize(&mut self) {
         self.wtime = 0;
         self.btime = 0;
+        self.winc = 0;
+        self.binc = 0;
+        self.movestogo = 0;
         self.movetime = 0;
-        self.nodes = 0;
+        self.target_time = 0;
+        self.depth = DEFAULT_MAX_DEPTH;
+        self.max_nodes = 0;
+        self.search_moves.clear();
+        self.max_plies = 0;
+        self.reset_nodes();
+    }
+
+    fn past_max_plies(&self, board:&Board) -> bool {
+        let ply = self.ply(board);
+        ply >= MAX_SEARCH_PLY || (self.max_plies != 0 && ply >= self.max_plies)
+    }
+
+    // the search's roots are shallow clones without history, the game's positions come from `self.board`
+    fn start_line(&mut self) {
+        self.accumulators.clear();
+        self.position_keys = game_history(&self.board).into_iter().skip(1).rev().collect();
+        self.root_ply = self.board.ply();
+    }
+
+    // plies from the search's root, null moves included. not `board.depth()`, which pleco's
+    // undo_null_move leaves one too deep
+    fn ply(&self, board:&Board) -> u16 {
+        board.ply().saturating_sub(self.root_ply)
+    }
+
+    // `board` was already reached in the game or this line with the same side to move, and the
+    // side that repeated it could do so again, so it scores as a draw
+    fn repeats(&self, board:&Board) -> bool {
+        let key = position_key(board);
+        self.position_keys.iter().rev()
+            .take(board.rule_50() as usize)
+            // nothing before a null move can be repeated through it
+            .take_while(|&&seen| seen != NULL_MOVE_KEY)
+            .skip(1)
+            .step_by(2)
+            .any(|&seen| seen == key)
+    }
+
+    fn count_node(&self) {
+        self.nodes[self.thread_index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn count_qnode(&self) {
+        self.count_node();
+        self.qnodes[self.thread_index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    // summed over all search threads
+    fn total_nodes(&self) -> u64 {
+        self.nodes.iter().map(|count| count.load(Ordering::Relaxed)).sum()
+    }
+
+    fn total_qnodes(&self) -> u64 {
+        self.qnodes.iter().map(|count| count.load(Ordering::Relaxed)).sum()
+    }
+
+    fn reset_nodes(&self) {
+        for count in self.nodes.iter().chain(self.qnodes.iter()) {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+
+    // one root search of `depth` plies inside (alpha, beta), both from the side to move's point of view,
+    // e.g. a null window asks whether the position is worth at least beta. the bound says whether the
+    // score failed high (BOUND_LOWER), low (BOUND_UPPER) or is exact
+    fn search_window(&mut self, depth:u8, alpha:i32, beta:i32) -> (BitMove, i32, u8) {
+        let perspective = { if self.board.turn() == Player::White { 1 } else { -1 } };
+        let (low, high) = { if perspective == 1 { (alpha, beta) } else { (-beta, -alpha) } };
+
+        self.instant = Instant::now();
+        self.reset_nodes();
+        self.start_line();
+        self.node_limit = u64::MAX;
+        self.movetime = u32::MAX;
+        self.root_moves.clear();
+        self.search_stopped.store(false, Ordering::Relaxed);
+        self.search_finished.store(false, Ordering::Relaxed);
+
+        let mut board = self.board.shallow_clone();
+        let last_move = self.board.last_move().unwrap_or(BitMove::null());
+        let (best_move, score) = minimax(self, &mut board, depth, low, high, 0, last_move, true);
+        let score = score * perspective;
+
+        let bound = {
+            if score >= beta { BOUND_LOWER } else if score <= alpha { BOUND_UPPER } else { BOUND_EXACT }
+        };
+        (best_move, score, bound)
+    }
+
+    fn change_threads(&mut self, threads:usize) {
+        if threads != self.threads {
+            self.pool = Arc::new(ThreadPool::new(threads - 1));
+        }
+        self.threads = threads;
+        self.nodes = Arc::new((0..threads).map(|_| AtomicU64::new(0)).collect());
+        self.qnodes = Arc::new((0..threads).map(|_| AtomicU64::new(0)).collect());
     }
 
     fn transposition_find(&self, board:&mut Board) -> TranspositionObject {
-        let transpos_object = self.transposition_table[board.zobrist() as usize % (self.hash_table_size_mb * MB_TO_ITEMS)];
-        if transpos_object.hash != board.zobrist() {
+        // the sizes are clamped, but an empty table must never reach the `%` in `bucket`
+        if self.transposition_table.slots.is_empty() {
             return TranspositionObject::new();
         }
-        else
-        {
-            return transpos_object;
+        let key = position_key(board);
+        let bucket = self.bucket(key);
+        let mut transpos_object = match (bucket..bucket + BUCKET_SLOTS).map(|index| self.transposition_table.load(index)).find(|entry| entry.hash == key) {
+            Some(entry) => entry,
+            None => return TranspositionObject::new(),
+        };
+        // a move that can't be played here means the entry can't be trusted, its score neither
+        let mv = transpos_object.best_move;
+        if !mv.is_null() && (!board.pseudo_legal_move(mv) || !board.legal_move(mv)) {
+            transpos_object.best_move = BitMove::null();
         }
+        transpos_object
     }
 
-    fn transposition_store(&mut self, board:&Board, score:i32, best_move:BitMove, depth:u8) {
+    fn transposition_store(&self, board:&Board, score:i32, best_move:BitMove, depth:u8, bound:u8) {
+        if self.transposition_table.slots.is_empty() {
+            return;
+        }
+        let key = position_key(board);
         let transpos_object = TranspositionObject {
-            hash: board.zobrist(),
+            hash: key,
             score,
             depth,
+            bound,
+            generation: self.generation,
             best_move,
         };
 
-        let old_obj = self.transposition_table[board.zobrist() as usize % (self.hash_table_size_mb * MB_TO_ITEMS)];
+        // the same position, else an empty slot, else the stalest entry past the age limit, else the shallower one
+        let bucket = self.bucket(key);
+        let slots: Vec<(usize, TranspositionObject)> = (bucket..bucket + BUCKET_SLOTS).map(|index| (index, self.transposition_table.load(index))).collect();
+        let (index, old_obj) = slots.iter().find(|slot| slot.1.hash == key)
+            .or_else(|| slots.iter().find(|slot| slot.1.hash == 0))
+            .or_else(|| slots.iter().filter(|slot| self.age(&slot.1) >= self.hash_age_limit).max_by_key(|slot| self.age(&slot.1)))
+            .or_else(|| slots.iter().min_by_key(|slot| slot.1.depth))
+            .copied()
+            .unwrap();
+
+        // in a nearly full table every store evicts something, so a shallow entry doesn't push out a
+        // deeper one from this search, only older or shallower ones make room
+        let evicting = old_obj.hash != 0 && old_obj.hash != key;
+        if evicting && self.hashfull() > HASH_PRESSURE_HASHFULL && old_obj.generation == self.generation && depth < old_obj.depth {
+            return;
+        }
 
         if old_obj.hash == 0 {
-            self.entries_filled += 1
+            self.transposition_table.filled.fetch_add(1, Ordering::Relaxed);
         }
 
-        self.transposition_table[board.zobrist() as usize % (self.hash_table_size_mb * MB_TO_ITEMS)] = transpos_object;
+        self.transposition_table.store(index, transpos_object);
     }
 
-    fn change_hash_size(&mut self, new_size:usize) {
-        self.transposition_table.clear();
-        self.hash_table_size_mb = new_size;
-        self.transposition_table = vec![TranspositionObject::new(); new_size * MB_TO_ITEMS];
-        self.entries_filled = 0;
+    // first slot of the bucket a key lives in
+    fn bucket(&self, key:u64) -> usize {
+        key as usize % (self.hash_table_size_mb * MB_TO_ITEMS) / BUCKET_SLOTS * BUCKET_SLOTS
     }
 
-}
-
-fn futile(board:&Board, depth:u8, alpha:i32) -> bool {
-
-    let stand_pat = evaluate(board);
-
-    let futility_margin: u32 = 300 * depth as u32 * depth as u32;
-
-    if futility_margin as i32 + stand_pat < alpha {
-        true
-    } else { false }
-
-}
+    // searches since the entry was stored
+    fn age(&self, entry:&TranspositionObject) -> u8 {
+        self.generation.wrapping_sub(entry.generation) & GENERATION_MASK
+    }
 
-fn gen_and_order_moves(board:&mut Board) -> MoveList {
-    let moves = board.generate_moves();
+    fn entries_filled(&self) -> usize {
+        self.transposition_table.filled.load(Ordering::Relaxed)
+    }
 
-    if moves.len() < 2 {
-        return moves;
+    // the best line just got worse, so think longer (still bounded by the hard limit).
+    // scores are from the side to move's point of view
+    fn extend_on_score_drop(&mut self, previous_score:i32, score:i32) {
+        if previous_score - score >= PANIC_SCORE_DROP {
+            self.target_time = self.target_time.saturating_mul(PANIC_TIME_FACTOR).min(self.movetime);
+        }
     }
 
-    let mut moves_scores: Vec<(BitMove, u8)> = Vec::default();
+    // permille of table slots in use, as reported by UCI `hashfull`
+    fn hashfull(&self) -> usize {
+        self.entries_filled() * 1000 / self.transposition_table.slots.len().max(1)
+    }
 
-    for i in 0..moves.len() {
-        if moves[i].is_capture() {
-            moves_scores.push((moves[i],6));
-            continue;
-        }
-        if board.gives_check(moves[i]) {
-            moves_scores.push((moves[i],5));
-            continue;
+    fn change_hash_size(&mut self, new_size:usize) {
+        let new_size = new_size.max(MIN_HASH_MB);
+        // a slot is the two words of `TranspositionTable`, not the unpacked TranspositionObject
+        let needed = new_size * MB_TO_ITEMS * std::mem::size_of::<[AtomicU64; 2]>();
+        if let Some(available) = available_memory() {
+            if needed > available / 100 * HASH_MAX_MEMORY_PERCENT {
+                info_string(&format!("Hash {} MB needs {} MB, more than {}% of the {} MB available, keeping {} MB",
+                    new_size, needed / (1024 * 1024), HASH_MAX_MEMORY_PERCENT, available / (1024 * 1024), self.hash_table_size_mb));
+                return;
+            }
         }
-        moves_scores.push((moves[i],0));
+
+        self.hash_table_size_mb = new_size;
+        self.transposition_table = Arc::new(TranspositionTable::new(new_size));
     }
 
-    moves_scores.sort_by_key(|k| k.1);
-    moves_scores.reverse();
+    // header (magic, version, size in MB) followed by (index, hash, score, depth, bound, move) for every used slot
+    fn dump_hash(&self, path:&str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
 
-    let mut new_moves = board.generate_moves();
+        writer.write_all(HASH_DUMP_MAGIC)?;
+        writer.write_all(&HASH_DUMP_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.hash_table_size_mb as u64).to_le_bytes())?;
 
-    for i in 0..moves_scores.len() {
-        new_moves[i] = moves_scores[i].0;
+        for index in 0..self.transposition_table.slots.len() {
+            let entry = self.transposition_table.load(index);
+            if entry.hash == 0 {
+                continue;
+            }
+            writer.write_all(&(index as u64).to_le_bytes())?;
+            writer.write_all(&entry.hash.to_le_bytes())?;
+            writer.write_all(&entry.score.to_le_bytes())?;
+            writer.write_all(&[entry.depth, entry.bound])?;
+            writer.write_all(&entry.best_move.get_raw().to_le_bytes())?;
+        }
+
+        writer.flush()
     }
 
-    return new_moves;
-}
+    fn load_hash(&mut self, path:&str) -> io::Result<()> {
+        let mut reader = BufReader::new(File::open(path)?);
 
-fn evaluate(board:&Board) -> i32 {
-    let mut eval:i32 = 0;
+        let mut magic = [0u8; 8];
+        let mut version = [0u8; 4];
+        let mut size_mb = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        reader.read_exact(&mut version)?;
+        reader.read_exact(&mut size_mb)?;
 
-    let game_stage: u8 = { if board.count_all_pieces() < 14 { 1 } else { 0 } };
-
-    static NONE_TABLE: [i32; 64] = [
-        0,  0,  0,  0,  0,  0,  0,  0,
-        0,  0,  0,  0,  0,  0,  0,  0,
-        0,  0,  0,  0,  0,  0,  0,  0,
-        0,  0,  0,  0,  0,  0,  0,  0,
-        0,  0,  0,  0,  0,  0,  0,  0,
-        0,  0,  0,  0,  0,  0,  0,  0,
-        0,  0,  0,  0,  0,  0,  0,  0,
-        0,  0,  0,  0,  0,  0,  0,  0,
-    ];
+        if &magic != HASH_DUMP_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a hash dump"));
+        }
+        if u32::from_le_bytes(version) != HASH_DUMP_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported hash dump version {}", u32::from_le_bytes(version))));
+        }
+        let size_mb = u64::from_le_bytes(size_mb) as usize;
+        if size_mb != self.hash_table_size_mb {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("dump is for a {} MB table, current Hash is {} MB", size_mb, self.hash_table_size_mb)));
+        }
 
-    static MG_PAWN_TABLE: [i32; 64] = [
-        0,  0,  0,  0,  0,  0,  0,  0,
-        50, 50, 50, 50, 50, 50, 50, 50,
-        10, 10, 20, 30, 30, 20, 10, 10,
-         5,  5, 10, 25, 25, 10,  5,  5,
-         0,  0,  0, 20, 20,  0,  0,  0,
-         5, -5,-10,  0,  0,-10, -5,  5,
-         5, 10, 10,-20,-20, 10, 10,  5,
-         0,  0,  0,  0,  0,  0,  0,  0
-    ];
+        let table = TranspositionTable::new(size_mb);
+        let mut record = [0u8; 24];
 
-    static EG_PAWN_TABLE: [i32; 64] = [
-        0,  0,  0,  0,  0,  0,  0,  0,
-        80, 80, 80, 80, 80, 80, 80, 80,
-        50, 50, 50, 50, 50, 50, 50, 50,
-        30, 30, 30, 30, 30, 30, 30, 30,
-        10, 10, 10, 10, 10, 10, 10, 10,
-        10, 10, 10, 10, 10, 10, 10, 10,
-        -5, -5, -5, -5, -5, -5, -5, -5,
-         0,  0,  0,  0,  0,  0,  0,  0
-    ];
+        loop {
+            match reader.read_exact(&mut record) {
+                Ok(()) => (),
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(error),
+            }
 
-    static MG_KNIGHT_TABLE: [i32; 64] = [
-        -50,-40,-30,-30,-30,-30,-40,-50,
-        -40,-20,  0,  0,  0,  0,-20,-40,
-        -30,  0, 10, 15, 15, 10,  0,-30,
-        -30,  5, 15, 20, 20, 15,  5,-30,
-        -30,  0, 15, 20, 20, 15,  0,-30,
-        -30,  5, 10, 15, 15, 10,  5,-30,
-        -40,-20,  0,  5,  5,  0,-20,-40,
-        -50,-40,-30,-30,-30,-30,-40,-50,
-    ];
+            let index = u64::from_le_bytes(record[0..8].try_into().unwrap()) as usize;
+            if index >= table.slots.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "entry index out of range"));
+            }
 
-    static MG_BISHOP_TABLE: [i32; 64] = [
-        -20,-10,-10,-10,-10,-10,-10,-20,
-        -10,  0,  0,  0,  0,  0,  0,-10,
-        -10,  0,  5, 10, 10,  5,  0,-10,
-        -10,  5,  5, 10, 10,  5,  5,-10,
-        -10,  0, 10, 10, 10, 10,  0,-10,
-        -10, 10, 10, 10, 10, 10, 10,-10,
-        -10,  5,  0,  0,  0,  0,  5,-10,
-        -20,-10,-10,-10,-10,-10,-10,-20,
-    ];
+            table.store(index, TranspositionObject {
+                hash: u64::from_le_bytes(record[8..16].try_into().unwrap()),
+                score: i32::from_le_bytes(record[16..20].try_into().unwrap()),
+                depth: record[20],
+                bound: record[21],
+                // entries from a dump count as fresh
+                generation: self.generation,
+                best_move: BitMove::new(u16::from_le_bytes(record[22..24].try_into().unwrap())),
+            });
+            table.filled.fetch_add(1, Ordering::Relaxed);
+        }
 
-    static MG_ROOK_TABLE: [i32; 64] = [
-        0,  0,  0,  0,  0,  0,  0,  0,
-        5, 10, 10, 10, 10, 10, 10,  5,
-       -5,  0,  0,  0,  0,  0,  0, -5,
-       -5,  0,  0,  0,  0,  0,  0, -5,
-       -5,  0,  0,  0,  0,  0,  0, -5,
-       -5,  0,  0,  0,  0,  0,  0, -5,
-       -5,  0,  0,  0,  0,  0,  0, -5,
-        0,  0,  0,  5,  5,  0,  0,  0
-    ];
+        self.transposition_table = Arc::new(table);
+        Ok(())
+    }
 
-    static MG_QUEEN_TABLE: [i32; 64] = [
-        -20,-10,-10, -5, -5,-10,-10,-20,
-        -10,  0,  0,  0,  0,  0,  0,-10,
-          0,  0,  5, -5, -5,  5,  0,  0,
-         -5,  0, -5,  5,  5, -5,  0, -5,
-         -5,  0, -5,  5,  5, -5,  0, -5,
-        -10,  5,  5, -5, -5,  5,  5,-10,
-        -10,  0,  5,  0,  0,  5,  0,-10,
-        -20,-10,-10, -5, -5,-10,-10,-20
-    ];
+    fn update_history(&mut self, board:&Board, cutoff_move:BitMove, quiets_tried:&[BitMove], depth:u8) {
+        let bonus = depth as i32 * depth as i32;
+        let side = board.turn() as usize;
 
-    static MG_KING_TABLE: [i32; 64] = [
-        -30,-40,-40,-50,-50,-40,-40,-30,
-        -30,-40,-40,-50,-50,-40,-40,-30,
-        -30,-40,-40,-50,-50,-40,-40,-30,
-        -30,-40,-40,-50,-50,-40,-40,-30,
-        -20,-30,-30,-40,-40,-30,-30,-20,
-        -10,-20,-20,-20,-20,-20,-20,-10,
-         20, 20,  0,  0,  0,  0, 20, 20,
-         20, 30, 10,  0,  0, 10, 30, 20
-    ];
+        // quiet moves searched before the cutoff move did not refute, so they are penalized
+        for mv in quiets_tried {
+            let entry = &mut self.history[side][mv.get_src_u8() as usize][mv.get_dest_u8() as usize];
+            *entry = (*entry - bonus).max(-HISTORY_MAX);
+        }
 
-    static EG_KING_TABLE: [i32; 64] = [
-        -50,-40,-30,-20,-20,-30,-40,-50,
-        -30,-20,-10,  0,  0,-10,-20,-30,
-        -30,-10, 20, 30, 30, 20,-10,-30,
-        -30,-10, 30, 40, 40, 30,-10,-30,
-        -30,-10, 30, 40, 40, 30,-10,-30,
-        -30,-10, 20, 30, 30, 20,-10,-30,
-        -30,-30,  0,  0,  0,  0,-30,-30,
-        -50,-30,-30,-30,-30,-30,-30,-50
-    ];
+        let entry = &mut self.history[side][cutoff_move.get_src_u8() as usize][cutoff_move.get_dest_u8() as usize];
+        *entry = (*entry + bonus).min(HISTORY_MAX);
+    }
 
-    static PIECE_TABLES_ALL: [[[i32; 64]; 7]; 2] = [
-        [
-            NONE_TABLE, MG_PAWN_TABLE, MG_KNIGHT_TABLE, MG_BISHOP_TABLE, MG_ROOK_TABLE, MG_QUEEN_TABLE, MG_KING_TABLE
-        ],
-        [
-            NONE_TABLE, EG_PAWN_TABLE, MG_KNIGHT_TABLE, MG_BISHOP_TABLE, MG_ROOK_TABLE, MG_QUEEN_TABLE, EG_KING_TABLE
-        ]
-    ];
+    // `Opening Variety` chooses between the root's nearly equal moves on this board
+    fn opening_variety_active(&self, board:&Board) -> bool {
+        self.opening_variety != 0 && !self.analyse_mode && board.moves_played() < OPENING_VARIETY_PLIES
+    }
 
-    static PIECE_VALUES: [i32; 7] = [
-        0, 100, 320, 330, 500, 900, 0
-    ];
+    fn pawn_entry(&mut self, board:&Board) -> PawnHashEntry {
+        let key = board.pawn_key();
+        let entry = &mut self.pawn_hash[key as usize % PAWN_HASH_ENTRIES];
 
-    if board.checkmate() {
-        let x:i32 = board.moves_played().into();
-        if board.turn() == Player::White {
-            return -9999999 + x;
-        } else {
-            return 9999999 - x;
+        if entry.key != key {
+            let (pawn_attacks, pawn_attack_spans) = pawn_attacks_and_spans(board);
+            *entry = PawnHashEntry { key, score: pawn_structure(board), pawn_attacks, pawn_attack_spans };
         }
+        *entry
     }
-    if board.stalemate() {
-        return 0;
+
+    fn pawn_structure(&mut self, board:&Board) -> i32 {
+        self.pawn_entry(board).score
     }
-    for location in 0..64 {
-        let square = SQ(location);
-        let piece: Piece = board.piece_at_sq(square);
 
-        if piece == Piece::None { continue };
+    fn eval_info(&mut self, board:&Board) -> EvalInfo {
+        let entry = self.pawn_entry(board);
+        EvalInfo::with_pawns(board, entry.pawn_attacks, entry.pawn_attack_spans)
+    }
 
-        if piece as usize % 8 != piece as usize {
-            eval -= PIECE_VALUES[piece as usize % 8];
-        } else {
-            eval += PIECE_VALUES[piece as usize % 8];
+    fn probe_eval_cache(&mut self, key:u64) -> Option<i32> {
+        if self.eval_cache.is_empty() {
+            return None;
         }
+        self.eval_cache_probes += 1;
+        let entry = self.eval_cache[key as usize % self.eval_cache.len()];
+        if entry.key != key {
+            return None;
+        }
+        self.eval_cache_hits += 1;
+        Some(entry.score)
+    }
 
-        
-        if piece.player().unwrap() == Player::White 
-        {
-            eval += PIECE_TABLES_ALL[game_stage as usize][piece.type_of() as usize][63-location as usize];
-        } 
-        else if piece.player().unwrap() == Player::Black 
-        {
-            eval -= PIECE_TABLES_ALL[game_stage as usize][piece.type_of() as usize][location as usize];
+    fn store_eval_cache(&mut self, key:u64, score:i32) {
+        if self.eval_cache.is_empty() {
+            return;
         }
+        let index = key as usize % self.eval_cache.len();
+        self.eval_cache[index] = EvalCacheEntry { key, score };
     }
-    eval
-}
 
-fn minimax(engine:&mut Engine, board:&mut Board, depth:u8, mut alpha:i32, mut beta:i32, search_extensions: u8) -> (BitMove, i32) {
-    let moves = gen_and_order_moves(board); // gen moves and order
-    if depth == 0 || moves.is_empty() {
-        (*engine).nodes += 1;
-        return (BitMove::null(), evaluate(board));
+    fn resize_eval_cache(&mut self, size_mb:usize) {
+        self.eval_cache_mb = size_mb;
+        self.eval_cache = vec![EvalCacheEntry { key: 0, score: 0 }; eval_cache_entries(size_mb)];
     }
 
-    let possible_transposition = (*engine).transposition_find(board);
-    if possible_transposition.best_move != BitMove::null() {
+    fn clear_eval_cache(&mut self) {
+        self.eval_cache.fill(EvalCacheEntry { key: 0, score: 0 });
+    }
 
-        if possible_transposition.depth >= depth {
-            return (possible_transposition.best_move, possible_transposition.score);
+    // from white's point of view like every other score
+    fn draw_score(&self, board:&Board) -> i32 {
+        if self.analyse_mode {
+            return 0;
         }
 
-
+        let side_to_move = { if board.turn() == Player::White { self.draw_value } else { -self.draw_value } };
+        side_to_move + self.draw_offset_score(board)
     }
 
+    fn draw_offset_score(&self, board:&Board) -> i32 {
+        // `self.board` is the root, its side to move is the one we play
+        let perspective = { if self.board.turn() == Player::White { 1 } else { -1 } };
+        if !self.contempt_dynamic || self.draw_offset * perspective >= 0 {
+            return self.draw_offset;
+        }
 
-    if (*engine).out_of_time() {
-        return 
-            if board.turn() == Player::White 
-            {(BitMove::null(),-1)} 
-            else 
-            {(BitMove::null(),-1)}
-    }
+        let material = |player:Player| -> i32 {
+            [PieceType::P, PieceType::N, PieceType::B, PieceType::R, PieceType::Q].iter()
+                .map(|&piece_type| board.count_piece(player, piece_type) as i32 * self.piece_values[piece_type as usize])
+                .sum()
+        };
+        let deficit = ((material(Player::White) - material(Player::Black)) * -perspective).clamp(0, CONTEMPT_DYNAMIC_RANGE);
 
-    let mut best_move = BitMove::null();
+        self.draw_offset * (CONTEMPT_DYNAMIC_RANGE - deficit) / CONTEMPT_DYNAMIC_RANGE
+    }
 
-    if board.turn() == Player::White {
-        for mv in moves {
-            board.apply_move(mv);
-            let eval = {
-                if (mv.is_capture()||board.in_check()) && search_extensions < MAX_EXTENSIONS {
-                    minimax(engine, board, depth, alpha, beta, search_extensions + 1)
-                }
-                else 
-                {
-                    if depth > 3 {
-                        minimax(engine, board, depth - 1, alpha, beta, search_extensions)
-                    }
-                    else {
-                        if futile(board, depth, alpha) {
-                            (mv, alpha - 2)
-                        }
-                        else {
-                            minimax(engine, board, depth - 1, alpha, beta, search_extensions)
-                        }
-                    }
-                    
-                }
-            };
-            board.undo_move();
+    // best move first, then the searched moves by score for the side to move, then any that a cutoff skipped
+    fn store_root_order(&mut self, board:&Board, moves:&MoveList, mut root_scores:Vec<(BitMove, i32)>, best_move:BitMove) {
+        let perspective = { if board.turn() == Player::White { 1 } else { -1 } };
+        root_scores.sort_by_key(|&(mv, score)| (mv != best_move, -score * perspective));
 
-            if eval.0.is_null() && eval.1 == -1 {
-                return (BitMove::null(),-1);
+        self.root_order = root_scores.iter().map(|&(mv, _)| mv).collect();
+        for &mv in moves.iter() {
+            if !self.root_order.contains(&mv) {
+                self.root_order.push(mv);
             }
+        }
+        self.root_order_key = position_key(board);
+    }
 
-            if alpha < eval.1 {
-                alpha = eval.1;
-                best_move = mv;
-            }
-            
-            if beta <= alpha {
-                break;
+    fn log_search(&self, line:&str) {
+        if let Some(log) = &self.search_log {
+            // a failing log must not disturb the search, the flush at the end reports it
+            let _ = writeln!(log.lock().unwrap(), "{}", line);
+        }
+    }
+
+    fn flush_search_log(&self) {
+        if let Some(log) = &self.search_log {
+            if let Err(error) = log.lock().unwrap().flush() {
+                info_string(&format!("could not write the search log: {}", error));
             }
         }
-        (*engine).transposition_store(board, alpha, best_move, depth);
-        return (best_move,alpha)
     }
-    else {
-        for mv in moves {
-            board.apply_move(mv);
-            let eval = {
-                if (mv.is_capture()||board.in_check()) && search_extensions < MAX_EXTENSIONS {
-                    minimax(engine, board, depth, alpha, beta, search_extensions + 1)
-                }
-                else 
-                {
 
-                    if depth > 3 {
-                        minimax(engine, board, depth - 1, alpha, beta, search_extensions)
-                    }
-                    else {
-                        if futile(board, depth, -beta) {
-                            (mv, beta + 2)
-                        }
-                        else {
-                            minimax(engine, board, depth - 1, alpha, beta, search_extensions)
-                        }
-                    }
-                }
-            };
-            board.undo_move();
+    fn active_network(&self) -> Option<&Arc<Network>> {
+        if self.use_nnue { self.network.as_ref() } else { None }
+    }
 
-            if eval.0.is_null() && eval.1 == -1 {
-                return (BitMove::null(),-1);
+    // apply_move that also keeps the network's accumulator up to date.
+    // only the moved and captured pieces change, castling and en passant are simply recomputed
+    fn make_move(&mut self, board:&mut Board, mv:BitMove) {
+        self.position_keys.push(position_key(board));
+        let network = match self.active_network() {
+            Some(network) => Arc::clone(network),
+            None => {
+                board.apply_move(mv);
+                return;
             }
+        };
 
+        let key = position_key(board);
+        let parent = self.accumulators.last().filter(|entry| entry.0 == key).map(|entry| entry.1.clone());
+        let moved = board.piece_at_sq(mv.get_src());
+        let captured = board.piece_at_sq(mv.get_dest());
 
-            if eval.1 < beta {
-                beta = eval.1;
-                best_move = mv;
-            }
-            if beta <= alpha {
-                break;
+        board.apply_move(mv);
+
+        let accumulator = match parent {
+            Some(mut accumulator) if !mv.is_castle() && !mv.is_en_passant() => {
+                network.update(&mut accumulator, moved, mv.get_src(), -1);
+                if captured != Piece::None {
+                    network.update(&mut accumulator, captured, mv.get_dest(), -1);
+                }
+                network.update(&mut accumulator, board.piece_at_sq(mv.get_dest()), mv.get_dest(), 1);
+                accumulator
             }
+            _ => network.refresh(board),
+        };
+        self.accumulators.push((position_key(board), accumulator));
+    }
+
+    fn unmake_move(&mut self, board:&mut Board) {
+        board.undo_move();
+        self.position_keys.pop();
+        if self.active_network().is_some() {
+            self.accumulators.pop();
         }
-        (*engine).transposition_store(board, beta, best_move, depth);
-        return (best_move,beta)
     }
+
+    // passes the turn, for null-move pruning to build on. only the side to move, en passant square and key change.
+    // no piece moves, so the accumulator is the parent's under the new key. the pawn hash is keyed
+    // on pawns only and needs nothing. the side to move must not be in check
+    fn make_null_move(&mut self, board:&mut Board) {
+        self.position_keys.push(NULL_MOVE_KEY);
+        let network = self.active_network().map(Arc::clone);
+        let key = position_key(board);
+        let parent = self.accumulators.last().filter(|entry| entry.0 == key).map(|entry| entry.1.clone());
+
+        unsafe { board.apply_null_move(); }
+
+        if let Some(network) = network {
+            let accumulator = parent.unwrap_or_else(|| network.refresh(board));
+            self.accumulators.push((position_key(board), accumulator));
+        }
+    }
+
+    fn unmake_null_move(&mut self, board:&mut Board) {
+        unsafe { board.undo_null_move(); }
+        self.position_keys.pop();
+        if self.active_network().is_some() {
+            self.accumulators.pop();
+        }
+    }
+
+    fn clear_history(&mut self) {
+        self.history = [[[0; 64]; 64]; 2];
+        self.countermoves = [[BitMove::null(); 64]; 16];
+    }
+
+    fn countermove(&self, board:&Board, prev_move:BitMove) -> BitMove {
+        if prev_move.is_null() {
+            return BitMove::null();
+        }
+        let piece = board.piece_at_sq(prev_move.get_dest());
+        self.countermoves[piece as usize][prev_move.get_dest_u8() as usize]
+    }
+
+    fn store_countermove(&mut self, board:&Board, prev_move:BitMove, refutation:BitMove) {
+        if prev_move.is_null() {
+            return;
+        }
+        let piece = board.piece_at_sq(prev_move.get_dest());
+        self.countermoves[piece as usize][prev_move.get_dest_u8() as usize] = refutation;
+    }
+
 }
 
-fn search(engine:&mut Engine) {
+// anything that isn't part of the protocol goes to the GUI as `info string` lines, one per line of text
+fn info_string(text:&str) {
+    for line in info_string_lines(text) {
+        println!("{}", line);
+    }
+}
 
-    let mut shallow_board = (*engine).board.shallow_clone();
-    
-    let mut depth = 0;
+fn info_string_lines(text:&str) -> Vec<String> {
+    text.lines().map(|line| format!("info string {}", line.trim())).collect()
+}
 
-    let mut best_move_info: (BitMove, i32) = (BitMove::null(), 0);
+// `Hash` values outside the range `uci` advertises are brought into it, never allocated as asked
+fn clamp_hash_mb(size:usize) -> usize {
+    size.clamp(MIN_HASH_MB, MAX_HASH_MB)
+}
 
-    (*engine).instant = Instant::now();
+// the UCI score and the reason when the side to move has no legal move
+fn game_over(board:&Board) -> Option<(&'static str, &'static str)> {
+    if !board.generate_moves().is_empty() {
+        return None;
+    }
+    if board.in_check() { Some(("mate 0", "checkmate")) } else { Some(("cp 0", "stalemate")) }
+}
 
-    let perspective = {
-        if (*engine).board.turn() == Player::White {1} else {-1}
+// the move in `legal_moves` written as `text` in UCI notation
+fn find_move(legal_moves:&MoveList, text:&str) -> Option<BitMove> {
+    legal_moves.iter().find(|mv| mv.to_string() == text).copied()
+}
+
+// the legal move written as `text` in SAN (e4, Nbd7, exd8=Q+, O-O) as EPD files give them, or in UCI notation.
+// None when no move or more than one fits
+fn find_san_move(board:&Board, text:&str) -> Option<BitMove> {
+    let legal_moves = board.generate_moves();
+    let san = text.trim_end_matches(['+', '#', '!', '?']);
+    if let Some(mv) = find_move(&legal_moves, san) {
+        return Some(mv);
+    }
+    match san {
+        "O-O" | "0-0" => return legal_moves.iter().find(|mv| mv.is_king_castle()).copied(),
+        "O-O-O" | "0-0-0" => return legal_moves.iter().find(|mv| mv.is_queen_castle()).copied(),
+        _ => {}
+    }
+    if !san.is_ascii() {
+        return None;
+    }
+
+    // piece, whatever disambiguates it, the destination and a promotion. captures aren't checked
+    let san: String = san.chars().filter(|c| *c != 'x' && *c != '=').collect();
+    let (piece, rest) = match san.chars().next()? {
+        'N' => (PieceType::N, &san[1..]),
+        'B' => (PieceType::B, &san[1..]),
+        'R' => (PieceType::R, &san[1..]),
+        'Q' => (PieceType::Q, &san[1..]),
+        'K' => (PieceType::K, &san[1..]),
+        _ => (PieceType::P, &san[..]),
     };
+    let (rest, promotion) = match rest.chars().last()? {
+        'N' => (&rest[..rest.len() - 1], Some(PieceType::N)),
+        'B' => (&rest[..rest.len() - 1], Some(PieceType::B)),
+        'R' => (&rest[..rest.len() - 1], Some(PieceType::R)),
+        'Q' => (&rest[..rest.len() - 1], Some(PieceType::Q)),
+        _ => (rest, None),
+    };
+    if rest.len() < 2 {
+        return None;
+    }
+    let (from, to) = rest.split_at(rest.len() - 2);
 
-    while !(*engine).out_of_time() && depth < (*engine).depth {
-        let past_best_move_info = best_move_info;
+    let matching: Vec<BitMove> = legal_moves.iter().filter(|mv| {
+        !mv.is_castle()
+            && board.moved_piece(**mv).type_of() == piece
+            && mv.get_dest().to_string() == to
+            && from.chars().all(|c| mv.get_src().to_string().contains(c))
+            && (if mv.is_promo() { Some(mv.promo_piece()) } else { None }) == promotion
+    }).copied().collect();
+    match matching[..] {
+        [mv] => Some(mv),
+        _ => None,
+    }
+}
 
-        depth += 1;
+// a FEN split into its fields. the move counters are often left out, they don't change anything we look at
+fn board_from_fields(fields:&[&str]) -> Option<Board> {
+    match fields.len() {
+        4 => Board::from_fen(&format!("{} 0 1", fields.join(" "))).ok(),
+        6 => Board::from_fen(&fields.join(" ")).ok(),
+        _ => None,
+    }
+}
+
+// plays UCI moves on `board`, stopping at the first one that isn't legal there
+fn apply_uci_moves(board:&mut Board, moves:&[&str]) {
+    for word in moves.iter().map(|word| word.trim()).filter(|word| !word.is_empty()) {
+        match find_move(&board.generate_moves(), word) {
+            Some(mv) => board.apply_move(mv),
+            None => {
+                info_string(&format!("Illegal move: {}", word));
+                return;
+            }
+        }
+    }
+}
 
-        best_move_info = minimax(
-            engine,
-            &mut shallow_board, 
-            depth, 
-            MINIMUM_EVAL, 
-            MAXIMUM_EVAL,
-            MAX_EXTENSIONS,
-        );
+fn is_quiet(mv:BitMove) -> bool {
+    !mv.is_capture() && !mv.is_promo()
+}
 
-        if (*engine).out_of_time() {
-            /*if past_best_move_info.1 * perspective > best_move_info.1 * perspective {
-                best_move_info = past_best_move_info;
-            }*/
-            best_move_info = past_best_move_info;
+// `mv` was just made on `board`, so its check is the one `mv` gave
+// on a PV node every check is extended, `CheckExtension` or not
+fn extends(engine:&Engine, board:&Board, mv:BitMove, prev_move:BitMove, is_pv:bool) -> bool {
+    let capture = match engine.capture_extension {
+        CaptureExtension::All => mv.is_capture(),
+        CaptureExtension::Recaptures => mv.is_capture() && prev_move.is_capture() && mv.get_dest() == prev_move.get_dest(),
+        CaptureExtension::Off => false,
+    };
+    capture || ((engine.check_extension || is_pv) && board.in_check())
+}
+
+fn lmr_table(base:f32, divisor:f32) -> Vec<[f32; LMR_TABLE_SIZE]> {
+    (0..LMR_TABLE_SIZE).map(|depth| {
+        let mut row = [0.0; LMR_TABLE_SIZE];
+        for (move_index, reduction) in row.iter_mut().enumerate() {
+            *reduction = base + f32::ln(depth as f32) * f32::ln(move_index as f32) / divisor;
         }
+        row
+    }).collect()
+}
 
-        let pv = best_move_info.0;
+// less on PV nodes, whose moves are the likeliest to matter
+fn late_move_reduction(engine:&Engine, board:&Board, mv:BitMove, depth:u8, move_index:usize, is_pv:bool) -> u8 {
+    if depth < LMR_MIN_DEPTH || move_index < LMR_MIN_MOVE_INDEX || !is_quiet(mv) || board.in_check() || board.gives_check(mv) {
+        return 0;
+    }
 
-        println!("info depth {depth} time {} nodes {} score cp {} pv {}",(*engine).instant.elapsed().as_millis(),(*engine).nodes, best_move_info.1 * perspective, pv);
-        
-        //DEBUG (transposition table)
-        //println!("debug-transposition table filled: {} MB/{}.0 MB", (*engine).entries_filled as f64 / MB_TO_ITEMS as f64,((*engine).hash_table_size_mb));
+    let mut reduction = engine.lmr_table[depth as usize][move_index.min(LMR_TABLE_SIZE - 1)];
 
+    // moves that keep failing to cut are reduced harder, proven ones are barely reduced at all
+    let history = engine.history[board.turn() as usize][mv.get_src_u8() as usize][mv.get_dest_u8() as usize];
+    reduction -= history as f32 / HISTORY_REDUCTION_DIVISOR;
+    if is_pv {
+        reduction -= PV_REDUCTION_CUT;
     }
 
-    println!("bestmove {}", best_move_info.0);
+    reduction.clamp(0.0, (depth - 1) as f32) as u8
 }
 
-#[allow(unused)]
-fn com(text:&String, engine:&mut Engine) {
-    let split_line = text.trim().split(" ");
+fn insufficient_material(board:&Board) -> bool {
+    for player in [Player::White, Player::Black] {
+        if board.count_piece(player, PieceType::P) > 0
+            || board.count_piece(player, PieceType::R) > 0
+            || board.count_piece(player, PieceType::Q) > 0 {
+            return false;
+        }
+    }
 
-    let lvec: Vec<&str> = split_line.collect();
+    // lone kings, or a single minor piece between both sides
+    board.count_piece(Player::White, PieceType::N) + board.count_piece(Player::White, PieceType::B)
+        + board.count_piece(Player::Black, PieceType::N) + board.count_piece(Player::Black, PieceType::B) <= 1
+}
 
-    (*engine).re_initialize();
+// MAX_PHASE with all the pieces on the board down to 0 with only kings and pawns.
+// extra pieces from promotions don't push it past MAX_PHASE
+fn game_phase(board:&Board) -> i32 {
+    let phase: i32 = [PieceType::N, PieceType::B, PieceType::R, PieceType::Q].iter()
+        .map(|&piece_type| {
+            (board.count_piece(Player::White, piece_type) + board.count_piece(Player::Black, piece_type)) as i32 * PHASE_WEIGHTS[piece_type as usize]
+        })
+        .sum();
+    phase.clamp(0, MAX_PHASE)
+}
 
-    match lvec[0] {
-        "position" => {
-            
-            match lvec[1] {
+// keys of the game's positions since the last capture or pawn move, newest (`board` itself) first
+fn game_history(board:&Board) -> Vec<u64> {
+    let mut history = board.parallel_clone();
+    let mut keys = vec![position_key(&history)];
+    for _ in 0..board.rule_50() {
+        if history.last_move().is_none() {
+            break;
+        }
+        history.undo_move();
+        keys.push(position_key(&history));
+    }
+    keys
+}
 
-                "startpos" => {
-                    (*engine).board = Board::start_pos();
-                    // to determine whether an input is "position startpos"
-                    // or "position startpos moves xxxx xxxx"
-                    let mut there_are_moves = false;
+// one of `moves` that brings about a position already seen twice in the game, a claimable threefold repetition
+fn threefold_move(board:&Board, moves:&[BitMove]) -> Option<BitMove> {
+    let mut after = board.parallel_clone();
+    for &mv in moves {
+        after.apply_move(mv);
+        let repeated = draw_reason(&after, &game_history(&after)) == Some("threefold repetition");
+        after.undo_move();
+        if repeated {
+            return Some(mv);
+        }
+    }
+    None
+}
 
-                    for word in lvec {
+// pleco's own `stalemate()` also fires after 50 plies without a capture or pawn move
+fn is_stalemate(board:&Board) -> bool {
+    !board.in_check() && board.generate_moves().is_empty()
+}
 
-                        if word.trim() == "moves" {
-                            there_are_moves = true;
-                            continue;
-                        }
+fn is_fifty_move_draw(board:&Board) -> bool {
+    board.rule_50() as i32 >= FIFTY_MOVE_LIMIT
+}
 
-                        if there_are_moves {
-                            let success = (*engine).board.apply_uci_move(word.trim());
-                            assert!(success);
-                        }
+// the rule that makes the game on `board` a draw, None if none does. `history` holds the position keys
+// since the last capture or pawn move the way `game_history` collects them, `board` itself first.
+// a mate given on the move that reaches the fifty-move limit still counts
+fn draw_reason(board:&Board, history:&[u64]) -> Option<&'static str> {
+    if board.checkmate() {
+        None
+    }
+    else if is_stalemate(board) {
+        Some("stalemate")
+    }
+    else if history.iter().filter(|&&key| key == position_key(board)).count() >= 3 {
+        Some("threefold repetition")
+    }
+    else if is_fifty_move_draw(board) {
+        Some("fifty-move rule")
+    }
+    else if insufficient_material(board) {
+        Some("insufficient material")
+    }
+    else {
+        None
+    }
+}
 
-                    }
-                }
-                    
-                "fen" => {
+// the game's draws as an arbiter sees them, for game results, the root's threefold claim and `why`.
+// inside the tree the search checks each rule on its own and scores a repetition at the second occurrence
+fn adjudicate_draw(board:&Board, history:&[u64]) -> bool {
+    draw_reason(board, history).is_some()
+}
 
-                    let mut there_are_moves = false;
+// the result string if the game on `board` has ended, None otherwise
+fn game_result(board:&Board) -> Option<String> {
+    if board.checkmate() {
+        if board.turn() == Player::White { Some("0-1 {checkmate}".to_string()) } else { Some("1-0 {checkmate}".to_string()) }
+    }
+    else {
+        draw_reason(board, &game_history(board)).map(|reason| format!("1/2-1/2 {{{}}}", reason))
+    }
+}
 
-                    let mut fen_constructing = false;
+// in won endgames without pawns, drive the lone king to the edge and walk our king up to it
+fn mop_up(engine:&Engine, board:&Board) -> i32 {
+    if board.count_piece(Player::White, PieceType::P) + board.count_piece(Player::Black, PieceType::P) > 0 {
+        return 0;
+    }
 
-                    let mut fen_string: String = String::new().to_owned();
+    let material = |player:Player| -> i32 {
+        [PieceType::N, PieceType::B, PieceType::R, PieceType::Q].iter()
+            .map(|&piece_type| board.count_piece(player, piece_type) as i32 * engine.piece_values[piece_type as usize])
+            .sum()
+    };
 
-                    for word in &lvec {
+    let advantage = material(Player::White) - material(Player::Black);
+    if advantage.abs() < MOP_UP_MIN_ADVANTAGE {
+        return 0;
+    }
 
-                        match word.trim() {
+    let (winner, loser) = {
+        if advantage > 0 { (Player::White, Player::Black) } else { (Player::Black, Player::White) }
+    };
 
-                            "fen" => {
-                                fen_constructing = true;
-                                continue;
-                            }
+    let loser_file = board.king_sq(loser).file_idx_of_sq() as i32;
+    let loser_rank = board.king_sq(loser).rank_idx_of_sq() as i32;
+    let winner_file = board.king_sq(winner).file_idx_of_sq() as i32;
+    let winner_rank = board.king_sq(winner).rank_idx_of_sq() as i32;
 
-                            "moves" => {
-                                fen_constructing = false;
-                                there_are_moves = true;
-                                break;
-                            }
+    let centre_distance = (3 - loser_file).max(loser_file - 4) + (3 - loser_rank).max(loser_rank - 4);
+    let king_distance = (loser_file - winner_file).abs() + (loser_rank - winner_rank).abs();
 
-                            _ => {
-                                if fen_constructing {
-                                    fen_string += word;
-                                    fen_string += " ";
-                                    continue;
-                                } else {
-                                    continue;
-                                }
-                            },
+    let bonus = MOP_UP_EDGE_WEIGHT * centre_distance + MOP_UP_PROXIMITY_WEIGHT * (14 - king_distance);
 
-                        }
-                    }
+    if winner == Player::White { bonus } else { -bonus }
+}
 
-                    (*engine).board = Board::from_fen(&fen_string).unwrap_or_default();
+// doubled, isolated and passed pawns, from white's point of view.
+// only depends on where the pawns are, so `Engine::pawn_structure` caches it
+fn pawn_structure(board:&Board) -> i32 {
+    let mut score = 0;
 
-                    if there_are_moves {
-                        
-                        let mut flag = false;
+    for player in [Player::White, Player::Black] {
+        let pawns = board.piece_bb(player, PieceType::P);
+        let sign = { if player == Player::White { 1 } else { -1 } };
 
-                        for word in &lvec {
-                            if !flag {
-                                match word.trim() {
+        for file in 0..8 {
+            let on_file = (pawns & SQ(file).file_bb()).count_bits() as i32;
+            if on_file > 1 {
+                score -= sign * DOUBLED_PAWN_PENALTY * (on_file - 1);
+            }
+        }
 
-                                    "moves" => {
-                                        flag = true;
-                                        continue;
-                                    },
-    
-                                    _ => continue,
-    
-                                }
-                            }
-                            
-                            let success = (*engine).board.apply_uci_move(word.trim());
-                            assert!(success);
+        for square in pawns {
+            let file = square.file_idx_of_sq();
+            let neighbours = {
+                (if file > 0 { pawns & SQ(file - 1).file_bb() } else { BitBoard(0) })
+                    | (if file < 7 { pawns & SQ(file + 1).file_bb() } else { BitBoard(0) })
+            };
+            if neighbours.is_empty() {
+                score -= sign * ISOLATED_PAWN_PENALTY;
+            }
 
-                        }
-                        
-                    }
+            if board.pawn_passed(player, square) {
+                let rank = { if player == Player::White { square.rank_idx_of_sq() } else { 7 - square.rank_idx_of_sq() } };
+                score += sign * PASSED_PAWN_BONUS[rank as usize];
+            }
+        }
+    }
 
-                }
+    score
+}
 
-                _ => {
-                    println!("Unknown command: {}", text.trim())
-                }
+// in the endgame our king wants to escort our passed pawns and keep away from theirs,
+// the further a pawn has come the more the kings' distances to it matter
+fn passed_pawn_kings(board:&Board) -> i32 {
+    let mut score = 0;
+
+    for player in [Player::White, Player::Black] {
+        let sign = { if player == Player::White { 1 } else { -1 } };
+        let own_king = board.king_sq(player);
+        let enemy_king = board.king_sq(!player);
+
+        for square in board.piece_bb(player, PieceType::P) {
+            if !board.pawn_passed(player, square) {
+                continue;
             }
-            
-            
-            
-            
+            let rank = { if player == Player::White { square.rank_idx_of_sq() } else { 7 - square.rank_idx_of_sq() } } as i32;
+            // Chebyshev distance, the number of king moves to reach the pawn
+            let distances = enemy_king.distance(square) as i32 - own_king.distance(square) as i32;
+            score += sign * PASSED_PAWN_KING_WEIGHT * rank * distances;
         }
-        
-        "go" => {
+    }
 
-            for i in 1..lvec.len() {
+    score
+}
 
-                match lvec[i] {
+// rule of the square: with only kings and pawns left, a passed pawn queens if the enemy king
+// is further from the promotion square than the pawn, counting the tempo of whoever is to move
+fn unstoppable_pawns(board:&Board) -> i32 {
+    for piece_type in [PieceType::N, PieceType::B, PieceType::R, PieceType::Q] {
+        if board.count_piece(Player::White, piece_type) + board.count_piece(Player::Black, piece_type) > 0 {
+            return 0;
+        }
+    }
 
-                    "depth" => {
-                        (*engine).depth = lvec[i+1].trim().parse::<u8>().unwrap_or_default(); 
-                    }
+    let mut score = 0;
 
-                    "wtime" => {
-                        (*engine).wtime = lvec[i+1].trim().parse::<u32>().unwrap_or_default();
-                    }
+    for player in [Player::White, Player::Black] {
+        let sign = { if player == Player::White { 1 } else { -1 } };
+        let enemy_king = board.king_sq(!player);
 
-                    "btime" => {
-                        (*engine).btime = lvec[i+1].trim().parse::<u32>().unwrap_or_default();
-                    }
+        for square in board.piece_bb(player, PieceType::P) {
+            if !board.pawn_passed(player, square) {
+                continue;
+            }
+            let rank = { if player == Player::White { square.rank_idx_of_sq() } else { 7 - square.rank_idx_of_sq() } } as i32;
+            let promotion = { if player == Player::White { SQ(56 + square.file_idx_of_sq()) } else { SQ(square.file_idx_of_sq()) } };
 
-                    "movetime" => {
-                        (*engine).movetime = lvec[i+1].trim().parse::<u32>().unwrap_or_default();
-                    }
+            // the double step from the starting rank saves a move
+            let pawn_moves = { if rank == 1 { 5 } else { 7 - rank } };
+            let king_moves = enemy_king.distance(promotion) as i32 - { if board.turn() == player { 0 } else { 1 } };
 
-                    _ => continue,
+            if pawn_moves < king_moves {
+                score += sign * UNSTOPPABLE_PAWN_BONUS;
+            }
+        }
+    }
 
-                }
+    score
+}
+
+// safe squares (not our own pieces, not attacked by enemy pawns) the pieces reach
+fn mobility(board:&Board, info:&EvalInfo) -> i32 {
+    let mut score = 0;
+
+    for player in [Player::White, Player::Black] {
+        let sign = { if player == Player::White { 1 } else { -1 } };
+        let safe = !board.get_occupied_player(player) & !info.pawn_attacks[!player as usize];
+
+        for piece_type in [PieceType::N, PieceType::B, PieceType::R, PieceType::Q] {
+            for square in board.piece_bb(player, piece_type) {
+                let reach = (board.attacks_from(piece_type, square, player) & safe).count_bits() as i32;
+                score += sign * MOBILITY_WEIGHTS[piece_type as usize] * reach;
             }
+        }
+    }
 
-            (*engine).movetime = {
-                if (*engine).movetime != 0 {
-                    (*engine).movetime
-                }
-                else if ((*engine).wtime != 0) || ((*engine).btime != 0) {
-                    if (*engine).board.turn() == Player::White {
-                        10*f32::sqrt((*engine).wtime as f32) as u32
-                    } else {
-                        10*f32::sqrt((*engine).btime as f32) as u32
-                    }
-                }
-                else {
-                    8000
-                }
-            };
+    score
+}
+
+fn outposts(board:&Board, info:&EvalInfo) -> i32 {
+    let mut score = 0;
+
+    for player in [Player::White, Player::Black] {
+        let sign = { if player == Player::White { 1 } else { -1 } };
+        let enemy_half = { if player == Player::White { BitBoard(0xFFFF_FFFF_0000_0000) } else { BitBoard(0x0000_0000_FFFF_FFFF) } };
+        let outpost_squares = enemy_half & info.pawn_attacks[player as usize] & !info.pawn_attack_spans[!player as usize];
+
+        score += sign * OUTPOST_BONUS * (board.piece_two_bb(PieceType::N, PieceType::B, player) & outpost_squares).count_bits() as i32;
+    }
+
+    score
+}
+
+// pawns sheltering the king against enemy pieces bearing on the squares around it
+fn king_safety(board:&Board, info:&EvalInfo) -> i32 {
+    let mut score = 0;
 
+    for player in [Player::White, Player::Black] {
+        let sign = { if player == Player::White { 1 } else { -1 } };
+        let zone = info.king_zones[player as usize];
 
-            (*engine).search_stopped = false;
-            search(engine)
+        let shield = (board.piece_bb(player, PieceType::P) & zone).count_bits() as i32 * PAWN_SHIELD_BONUS;
+
+        let mut danger = 0;
+        for piece_type in [PieceType::N, PieceType::B, PieceType::R, PieceType::Q] {
+            for square in board.piece_bb(!player, piece_type) {
+                danger += KING_ATTACK_WEIGHTS[piece_type as usize] * (board.attacks_from(piece_type, square, !player) & zone).count_bits() as i32;
+            }
         }
-        
-        "setoption" => {
-            match lvec[1] {
-                "name" => {
-                    match lvec[2] {
 
-                        "Hash" => {
-                            match lvec[3] {
-                                "value" => {
-                                    (*engine).change_hash_size(lvec[4].parse().expect("failed"));
-                                }
+        score += sign * (shield - danger);
+    }
 
-                                _ => println!("Unknown command: {}\n Try `setoption name Hash value _`", text.trim())
-                            }
-                        }
+    score
+}
 
+// attackers closing in on the enemy king, counted in king moves between them.
+// complements `king_safety`, which looks at the squares around the king instead
+fn king_tropism(board:&Board, phase:i32) -> i32 {
+    let mut score = 0;
 
-                        _ => println!("Unknown command: {}\n Maybe try `uci` and use a valid id from there?", text.trim())
-                    }
-                }
+    for player in [Player::White, Player::Black] {
+        let sign = { if player == Player::White { 1 } else { -1 } };
+        let enemy_king = board.king_sq(!player);
 
-                _ => println!("Unknown command: {}\n Maybe try setoption `name`?", text.trim())
+        for piece_type in [PieceType::N, PieceType::B, PieceType::R, PieceType::Q] {
+            for square in board.piece_bb(player, piece_type) {
+                score += sign * KING_TROPISM_WEIGHTS[piece_type as usize] * (7 - square.distance(enemy_king) as i32);
             }
         }
+    }
 
+    score * phase / MAX_PHASE
+}
 
-        "d" => {
-            (*engine).board.pretty_print()
+// words of a `go` command that are no parameter, no number after one and no searchmoves move,
+// most likely typos
+fn unknown_go_parameters<'a>(lvec:&[&'a str]) -> Vec<&'a str> {
+    let mut unknown: Vec<&str> = Vec::new();
+    let mut in_searchmoves = false;
+    for i in 1..lvec.len() {
+        let word = lvec[i].trim();
+        if GO_PARAMETERS.contains(&word) {
+            in_searchmoves = word == "searchmoves";
+        } else if !word.is_empty() && !in_searchmoves && !NUMERIC_GO_PARAMETERS.contains(&lvec[i-1]) {
+            unknown.push(word);
         }
-        "uci" => {
-            println!("id name TissousleBot");
-            println!("id author Tissousle");
-            println!("");
-            println!("option name Hash type spin default 16 min 1 max 4096");
-            println!("uciok");
-        },
-        "isready" => 
-            println!("readyok"),
-        "ucinewgame" => 
-            (),
-        "stop" => 
-            (*engine).search_stopped = true,
-        "quit" =>
-            (*engine).active = false,
-        _ => 
-            println!("Unknown command: {}", text.trim()),
     }
+    unknown
 }
 
-fn main() {
-    
-    
+// (target, maximum) thinking time in ms for the side to move, from the `go` clock parameters alone
+#[allow(clippy::too_many_arguments)]
+fn allocate_time(turn:Player, wtime:u32, btime:u32, winc:u32, binc:u32, movestogo:u32, movetime:u32, overhead:u32) -> (u32, u32) {
+    if movetime != 0 {
+        return (movetime, movetime);
+    }
 
-    let mut engine = //std::thread::Builder::new().stack_size(12_000_000).spawn(|| {
-        Engine::new(16);
-    //}).unwrap().join().unwrap();
+    if wtime == 0 && btime == 0 {
+        return (DEFAULT_MOVETIME, DEFAULT_MOVETIME);
+    }
 
-    
+    let (time, increment) = {
+        if turn == Player::White { (wtime, winc) } else { (btime, binc) }
+    };
+    let moves_to_go = { if movestogo != 0 { movestogo } else { DEFAULT_MOVES_TO_GO } };
 
-    while engine.active {
+    // the clock keeps running until our move reaches the GUI, so none of the overhead is ours to spend
+    let available = time.saturating_sub(overhead).max(1);
 
-        let mut text = String::new();
+    let target = (available / moves_to_go + increment * 3 / 4).min(available);
+    // never bet more than half the remaining clock on one move, unless it's the last one before the control
+    let ceiling = { if moves_to_go == 1 { available } else { available / 2 } };
+    let maximum = (target * MAXIMUM_TIME_FACTOR).min(ceiling).max(target);
 
-        io::stdin()
-            .read_line(&mut text)
-            .expect("Failed to read line");
+    (target, maximum)
+}
 
-        com(&text, &mut engine);
+// `board` is the position after the move, so the side that played it is the one not to move.
+// the move is futile if even futility_margin * depth^2 on top of the static eval can't reach its bound
+fn futile(engine:&mut Engine, board:&Board, depth:u8, alpha:i32, beta:i32) -> bool {
 
-        
+    if game_phase(board) < engine.futility_min_phase {
+        return false;
+    }
+
+    let stand_pat = evaluate(engine, board);
+
+    let futility_margin = engine.futility_margin * depth as i32 * depth as i32;
+
+    if board.turn() == Player::Black {
+        stand_pat + futility_margin < alpha
+    } else {
+        stand_pat - futility_margin > beta
     }
 
 }
+
+// `table_move` goes before the captures, the rest keep their order
+fn table_move_first(moves:&mut [BitMove], table_move:BitMove) {
+    if let Some(index) = moves.iter().position(|&mv| mv == table_move) {
+        moves[..=index].rotate_right(1);
+    }
+}
+
+// the previous iteration's order when it was searching this same root, the usual ordering otherwise
+fn gen_and_order_root_moves(engine:&Engine, board:&mut Board, prev_move:BitMove) -> MoveList {
+    let moves = gen_and_order_moves(engine, board, prev_move);
+    if engine.root_order_key != position_key(board) {
+        return moves;
+    }
+
+    let mut ordered: Vec<BitMove> = engine.root_order.iter().filter(|mv| moves.contains(mv)).copied().collect();
+    for &mv in moves.iter() {
+        if !ordered.contains(&mv) {
+            ordered.push(mv);
+        }
+    }
+    ordered.into_iter().collect()
+}
+
+fn gen_and_order_moves(engine:&Engine, board:&mut Board, prev_move:BitMove) -> MoveList {
+    let moves = board.generate_moves();
+
+    if moves.len() < 2 {
+        return moves;
+    }
+
+    let countermove = engine.countermove(board, prev_move);
+
+    let mut moves_scores: Vec<(BitMove, u8)> = Vec::default();
+
+    for i in 0..moves.len() {
+        // a queen promotion wins more than almost any capture, an underpromotion is only worth a look
+        // when it checks (a knight fork), otherwise it comes after every quiet move
+        if moves[i].is_promo() {
+            if moves[i].promo_piece() == PieceType::Q {
+                moves_scores.push((moves[i],7));
+            } else if board.gives_check(moves[i]) {
+                moves_scores.push((moves[i],5));
+            } else {
+                moves_scores.push((moves[i],0));
+            }
+            continue;
+        }
+        if moves[i].is_capture() {
+            moves_scores.push((moves[i],6));
+            continue;
+        }
+        if board.gives_check(moves[i]) {
+            moves_scores.push((moves[i],5));
+            continue;
+        }
+        if moves[i] == countermove {
+            moves_scores.push((moves[i],4));
+            continue;
+        }
+        moves_scores.push((moves[i],1));
+    }
+
+    moves_scores.sort_by_key(|k| k.1);
+    moves_scores.reverse();
+
+    let mut new_moves = board.generate_moves();
+
+    for i in 0..moves_scores.len() {
+        new_moves[i] = moves_scores[i].0;
+    }
+
+    return new_moves;
+}
+
+static NONE_TABLE: [i32; 64] = [
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+    0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+static MG_PAWN_TABLE: [i32; 64] = [
+    0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0
+];
+
+static EG_PAWN_TABLE: [i32; 64] = [
+    0,  0,  0,  0,  0,  0,  0,  0,
+    80, 80, 80, 80, 80, 80, 80, 80,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    30, 30, 30, 30, 30, 30, 30, 30,
+    10, 10, 10, 10, 10, 10, 10, 10,
+    10, 10, 10, 10, 10, 10, 10, 10,
+    -5, -5, -5, -5, -5, -5, -5, -5,
+     0,  0,  0,  0,  0,  0,  0,  0
+];
+
+static MG_KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+static MG_BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+static MG_ROOK_TABLE: [i32; 64] = [
+    0,  0,  0,  0,  0,  0,  0,  0,
+    5, 10, 10, 10, 10, 10, 10,  5,
+   -5,  0,  0,  0,  0,  0,  0, -5,
+   -5,  0,  0,  0,  0,  0,  0, -5,
+   -5,  0,  0,  0,  0,  0,  0, -5,
+   -5,  0,  0,  0,  0,  0,  0, -5,
+   -5,  0,  0,  0,  0,  0,  0, -5,
+    0,  0,  0,  5,  5,  0,  0,  0
+];
+
+static MG_QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+      0,  0,  5, -5, -5,  5,  0,  0,
+     -5,  0, -5,  5,  5, -5,  0, -5,
+     -5,  0, -5,  5,  5, -5,  0, -5,
+    -10,  5,  5, -5, -5,  5,  5,-10,
+    -10,  0,  5,  0,  0,  5,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20
+];
+
+static MG_KING_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20
+];
+
+static EG_KING_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50
+];
+
+// built-in tables, [stage][PieceType][square], replaced by a weights `EvalFile`
+static PIECE_TABLES_ALL: [[[i32; 64]; 7]; 2] = [
+    [
+        NONE_TABLE, MG_PAWN_TABLE, MG_KNIGHT_TABLE, MG_BISHOP_TABLE, MG_ROOK_TABLE, MG_QUEEN_TABLE, MG_KING_TABLE
+    ],
+    [
+        NONE_TABLE, EG_PAWN_TABLE, MG_KNIGHT_TABLE, MG_BISHOP_TABLE, MG_ROOK_TABLE, MG_QUEEN_TABLE, EG_KING_TABLE
+    ]
+];
+
+// (material, piece square tables) from white's point of view
+fn material_and_tables(engine:&Engine, board:&Board, game_stage:u8) -> (i32, i32) {
+    let mut material:i32 = 0;
+    let mut tables:i32 = 0;
+
+    for location in 0..64 {
+        let square = SQ(location);
+        let piece: Piece = board.piece_at_sq(square);
+
+        if piece == Piece::None { continue };
+
+        let piece_type = piece.type_of() as usize;
+        if piece.player() == Some(Player::White) {
+            material += engine.piece_values[piece_type];
+            tables += engine.piece_tables[game_stage as usize][piece_type][63-location as usize];
+        } else {
+            material -= engine.piece_values[piece_type];
+            tables -= engine.piece_tables[game_stage as usize][piece_type][location as usize];
+        }
+    }
+
+    (material, tables)
+}
+
+fn eval_cache_entries(size_mb:usize) -> usize {
+    size_mb * 1024 * 1024 / std::mem::size_of::<EvalCacheEntry>()
+}
+
+fn evaluate(engine:&mut Engine, board:&Board) -> i32 {
+    let mut eval:i32 = 0;
+
+    let phase = game_phase(board);
+    let game_stage: u8 = { if phase <= ENDGAME_PHASE { 1 } else { 0 } };
+
+    // one legal move generation settles both checkmate and stalemate
+    let no_moves = board.generate_moves().is_empty();
+    if no_moves && board.in_check() {
+        let x:i32 = board.moves_played().into();
+        if board.turn() == Player::White {
+            return -9999999 + x;
+        } else {
+            return 9999999 - x;
+        }
+    }
+    if no_moves || is_fifty_move_draw(board) || insufficient_material(board) {
+        return engine.draw_score(board);
+    }
+
+    if let Some(network) = engine.active_network() {
+        let eval = match engine.accumulators.last() {
+            Some((key, accumulator)) if *key == position_key(board) => network.output(accumulator),
+            _ => network.output(&network.refresh(board)),
+        };
+        return fifty_move_scale(engine, board, eval);
+    }
+
+    // the fifty-move counter isn't part of the key, so the cache holds the score before scaling
+    let key = position_key(board);
+    if let Some(eval) = engine.probe_eval_cache(key) {
+        return fifty_move_scale(engine, board, eval);
+    }
+
+    let (material, tables) = material_and_tables(engine, board, game_stage);
+    eval += material + tables;
+
+    if engine.evaluation_mode == EvaluationMode::Full {
+        let info = engine.eval_info(board);
+
+        eval += engine.pawn_structure(board);
+        eval += mobility(board, &info);
+        eval += outposts(board, &info);
+        eval += king_tropism(board, phase);
+
+        if game_stage == 0 {
+            eval += king_safety(board, &info);
+        } else {
+            eval += passed_pawn_kings(board);
+            eval += unstoppable_pawns(board);
+        }
+    }
+
+    if game_stage == 1 {
+        eval += mop_up(engine, board);
+    }
+
+    engine.store_eval_cache(key, eval);
+    fifty_move_scale(engine, board, eval)
+}
+
+fn fifty_move_scale(engine:&Engine, board:&Board, eval:i32) -> i32 {
+    let clock = board.rule_50() as i32;
+    if clock <= FIFTY_MOVE_SCALE_START {
+        return eval;
+    }
+
+    let draw = engine.draw_score(board);
+    draw + (eval - draw) * (FIFTY_MOVE_LIMIT - clock).max(0) / (FIFTY_MOVE_LIMIT - FIFTY_MOVE_SCALE_START)
+}
+
+// the classical terms `evaluate` adds up for `board`, each from white's point of view.
+// mate, draws and the network are left to the caller
+fn explain_evaluation(engine:&mut Engine, board:&Board) -> Vec<(&'static str, i32)> {
+    let phase = game_phase(board);
+    let game_stage: u8 = { if phase <= ENDGAME_PHASE { 1 } else { 0 } };
+    let (material, tables) = material_and_tables(engine, board, game_stage);
+    let mut terms = vec![("material", material), ("piece tables", tables)];
+
+    if engine.evaluation_mode == EvaluationMode::Full {
+        let info = engine.eval_info(board);
+
+        terms.push(("pawn structure", engine.pawn_structure(board)));
+        terms.push(("mobility", mobility(board, &info)));
+        terms.push(("outposts", outposts(board, &info)));
+        terms.push(("king tropism", king_tropism(board, phase)));
+
+        if game_stage == 0 {
+            terms.push(("king safety", king_safety(board, &info)));
+        } else {
+            terms.push(("passed pawn kings", passed_pawn_kings(board)));
+            terms.push(("unstoppable pawns", unstoppable_pawns(board)));
+        }
+    }
+
+    if game_stage == 1 {
+        terms.push(("mop up", mop_up(engine, board)));
+    }
+
+    terms
+}
+
+// `why`: the move of the last search, its score and line, and what the evaluation saw at the end of that line.
+// one line of text per fact, sent as `info string` lines
+fn explain_last_search(engine:&mut Engine) -> String {
+    if (*engine).pv.is_empty() || position_key(&(*engine).board) != (*engine).pv_root_key {
+        return String::from("No search to explain for this position, run `go` first");
+    }
+
+    let pv = (*engine).pv.iter().map(|mv| mv.to_string()).collect::<Vec<String>>().join(" ");
+    let mut lines = vec![format!("why bestmove {} score cp {} pv {}", (*engine).pv[0], (*engine).pv_score, pv)];
+
+    // with the game's moves kept, a line that repeats them is drawn like it would be in the game
+    let mut leaf = (*engine).board.parallel_clone();
+    for mv in (*engine).pv.clone() {
+        leaf.apply_move(mv);
+    }
+    lines.push(format!("why leaf {}", leaf.fen()));
+
+    if leaf.checkmate() {
+        lines.push(String::from("why leaf is checkmate"));
+        return lines.join("\n");
+    }
+    if adjudicate_draw(&leaf, &game_history(&leaf)) {
+        lines.push(format!("why leaf is a draw, scored {}", (*engine).draw_score(&leaf)));
+        return lines.join("\n");
+    }
+    if (*engine).active_network().is_some() {
+        lines.push(String::from("why the network evaluates this, the terms below are the classical evaluation"));
+    }
+
+    let terms = explain_evaluation(engine, &leaf);
+    for (name, score) in terms.iter() {
+        lines.push(format!("why {} {}", name, score));
+    }
+    lines.push(format!("why total {} from white's point of view", terms.iter().map(|(_, score)| score).sum::<i32>()));
+    lines.join("\n")
+}
+
+// the current value of every option `uci` lists, in the same order and as `setoption` takes it,
+// except UCI_Opponent which only keeps the rating and name
+fn option_values(engine:&Engine) -> Vec<(&'static str, String)> {
+    let opponent = match (*engine).opponent_rating {
+        Some(rating) => format!("{} {}", rating, (*engine).opponent_name),
+        None => String::from("<empty>"),
+    };
+    vec![
+        ("Hash", (*engine).hash_table_size_mb.to_string()),
+        ("HashAgeLimit", (*engine).hash_age_limit.to_string()),
+        ("EvalCache", (*engine).eval_cache_mb.to_string()),
+        ("Threads", (*engine).threads.to_string()),
+        ("Thread Mode", String::from(match (*engine).thread_mode { ThreadMode::LazySmp => "lazysmp", ThreadMode::RootSplit => "rootsplit" })),
+        ("Ponder", (*engine).ponder.to_string()),
+        ("Move Overhead", (*engine).move_overhead.to_string()),
+        ("NodesTime", (*engine).nodes_time.to_string()),
+        ("MultiPV", (*engine).multipv.to_string()),
+        ("MaxDepth", (*engine).max_depth.to_string()),
+        ("LMRBase", (*engine).lmr_base.to_string()),
+        ("LMRDivisor", (*engine).lmr_divisor.to_string()),
+        ("FutilityDepth", (*engine).futility_depth.to_string()),
+        ("FutilityMargin", (*engine).futility_margin.to_string()),
+        ("FutilityMinPhase", (*engine).futility_min_phase.to_string()),
+        ("CheckExtension", (*engine).check_extension.to_string()),
+        ("CaptureExtension", String::from(match (*engine).capture_extension { CaptureExtension::All => "all", CaptureExtension::Recaptures => "recaptures", CaptureExtension::Off => "none" })),
+        ("AspirationWindow", (*engine).aspiration_window.to_string()),
+        ("Skill Level", (*engine).skill_level.to_string()),
+        ("PawnValue", (*engine).piece_values[1].to_string()),
+        ("KnightValue", (*engine).piece_values[2].to_string()),
+        ("BishopValue", (*engine).piece_values[3].to_string()),
+        ("RookValue", (*engine).piece_values[4].to_string()),
+        ("QueenValue", (*engine).piece_values[5].to_string()),
+        ("DrawOffset", (*engine).draw_offset.to_string()),
+        ("ContemptDynamic", (*engine).contempt_dynamic.to_string()),
+        ("StableDraw", (*engine).stable_draw.to_string()),
+        ("DrawScore", (*engine).draw_value.to_string()),
+        ("Opening Variety", (*engine).opening_variety.to_string()),
+        ("UCI_AnalyseMode", (*engine).analyse_mode.to_string()),
+        ("UCI_ShowWDL", (*engine).show_wdl.to_string()),
+        ("Evaluation", String::from(match (*engine).evaluation_mode { EvaluationMode::Classic => "classic", EvaluationMode::Full => "full" })),
+        ("EvalFile", (*engine).eval_file.clone()),
+        ("SearchLog", (*engine).search_log_path.clone()),
+        ("UseNNUE", (*engine).use_nnue.to_string()),
+        ("UCI_Opponent", opponent),
+    ]
+}
+
+// `tune` data, one position per line: a FEN (counters optional) and the game's result for white,
+// 1-0 / 1/2-1/2 / 0-1 or 1.0 / 0.5 / 0.0, quoted or bracketed as EPD and texel sets write them.
+// returns the usable positions and the number of lines skipped
+fn load_tuning_positions(text:&str) -> (Vec<(Board, f64)>, usize) {
+    let mut positions = Vec::new();
+    let mut skipped = 0;
+
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = words.last().map(|word| word.trim_matches(|c| c == '"' || c == ';' || c == '[' || c == ']'));
+        let result = match result {
+            Some("1-0") | Some("1.0") => Some(1.0),
+            Some("1/2-1/2") | Some("0.5") => Some(0.5),
+            Some("0-1") | Some("0.0") => Some(0.0),
+            _ => None,
+        };
+
+        // placement, side, castling and en passant, then the counters if they are there
+        let counters = words.iter().skip(4).take(2).take_while(|word| word.parse::<u32>().is_ok()).count();
+        let board = board_from_fields(&words[..(4 + counters).min(words.len())]);
+
+        match (board, result) {
+            (Some(board), Some(result)) => positions.push((board, result)),
+            _ => skipped += 1,
+        }
+    }
+
+    (positions, skipped)
+}
+
+// `testsuite` data, EPD lines: the four FEN fields then `;` terminated operations, of which `bm`, `am`
+// and `id` are read, e.g. `<fen> bm Qg6; id "WAC.001";`. lines without a usable bm or am are skipped
+fn load_test_positions(text:&str) -> (Vec<TestPosition>, usize) {
+    let mut positions = Vec::new();
+    let mut skipped = 0;
+
+    for (line_index, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let board = match words.get(..4).and_then(board_from_fields) {
+            Some(board) => board,
+            None => {
+                skipped += 1;
+                continue;
+            }
+        };
+
+        let mut position = TestPosition { board, id: format!("line {}", line_index + 1), best_moves: Vec::new(), avoid_moves: Vec::new() };
+        let mut unknown_move = false;
+        for operation in words[4..].join(" ").split(';') {
+            let operands: Vec<&str> = operation.split_whitespace().collect();
+            match operands.first() {
+                Some(&"bm") | Some(&"am") => {
+                    for word in operands[1..].iter() {
+                        match find_san_move(&position.board, word) {
+                            Some(mv) if operands[0] == "bm" => position.best_moves.push(mv),
+                            Some(mv) => position.avoid_moves.push(mv),
+                            None => unknown_move = true,
+                        }
+                    }
+                }
+                Some(&"id") => position.id = operands[1..].join(" ").trim_matches('"').to_string(),
+                _ => {}
+            }
+        }
+
+        if unknown_move || (position.best_moves.is_empty() && position.avoid_moves.is_empty()) {
+            skipped += 1;
+        } else {
+            positions.push(position);
+        }
+    }
+
+    (positions, skipped)
+}
+
+// texel tuning error: mean squared difference between the results and the static eval mapped to a win chance
+fn tuning_error(engine:&mut Engine, positions:&[(Board, f64)]) -> f64 {
+    // the piece values just changed
+    (*engine).clear_eval_cache();
+    let total: f64 = positions.iter().map(|(board, result)| {
+        let expected = 1.0 / (1.0 + f64::powf(10.0, -TUNE_K * evaluate(engine, board) as f64 / 400.0));
+        (result - expected).powi(2)
+    }).sum();
+    total / positions.len() as f64
+}
+
+// one pass of coordinate descent over the piece values: each one keeps stepping up (or else down)
+// while that lowers the error. returns the error before and after
+fn tune_piece_values(engine:&mut Engine, positions:&[(Board, f64)]) -> (f64, f64) {
+    let start = tuning_error(engine, positions);
+    let mut best = start;
+
+    for piece_index in 1..=5 {
+        for direction in [1, -1] {
+            let mut improved = false;
+            for _ in 0..TUNE_MAX_STEPS {
+                engine.piece_values[piece_index] += direction * TUNE_STEP;
+                let error = tuning_error(engine, positions);
+                if error < best {
+                    best = error;
+                    improved = true;
+                } else {
+                    engine.piece_values[piece_index] -= direction * TUNE_STEP;
+                    break;
+                }
+            }
+            if improved {
+                break;
+            }
+        }
+    }
+
+    (start, best)
+}
+
+// static exchange evaluation: material won (cp) by `mv` if both sides keep recapturing on its
+// destination with their least valuable piece, each free to stop when that loses more.
+// pinned pieces don't recapture, and neither does a king onto a square still defended
+fn see(engine:&Engine, board:&Board, mv:BitMove) -> i32 {
+    if mv.is_castle() {
+        return 0;
+    }
+
+    let value = |piece_type:PieceType| engine.piece_values[piece_type as usize];
+    let to = mv.get_dest();
+    let mut occupied = (board.occupied() ^ mv.get_src().to_bb()) | to.to_bb();
+
+    // en passant takes a pawn that isn't on the destination square
+    if mv.is_en_passant() {
+        let captured_square = { if board.turn() == Player::White { SQ(to.0 - 8) } else { SQ(to.0 + 8) } };
+        occupied ^= captured_square.to_bb();
+    }
+
+    let mut gains = vec![value(board.captured_piece(mv))];
+    let mut on_square = board.moved_piece(mv).type_of();
+    if mv.is_promo() {
+        gains[0] += value(mv.promo_piece()) - value(PieceType::P);
+        on_square = mv.promo_piece();
+    }
+
+    let pinned = [board.pinned_pieces(Player::White), board.pinned_pieces(Player::Black)];
+    let mut side = !board.turn();
+    loop {
+        let attackers = board.attackers_to(to, occupied) & occupied & board.get_occupied_player(side) & !pinned[side as usize];
+        let next = [PieceType::P, PieceType::N, PieceType::B, PieceType::R, PieceType::Q, PieceType::K].iter()
+            .find_map(|&piece_type| (attackers & board.piece_bb(side, piece_type)).into_iter().next().map(|square| (piece_type, square)));
+        let (piece_type, square) = match next {
+            Some(next) => next,
+            None => break,
+        };
+
+        if piece_type == PieceType::K {
+            let defenders = board.attackers_to(to, occupied ^ square.to_bb()) & (occupied ^ square.to_bb()) & board.get_occupied_player(!side);
+            if !defenders.is_empty() {
+                break;
+            }
+        }
+
+        gains.push(value(on_square) - gains[gains.len() - 1]);
+        on_square = piece_type;
+        occupied ^= square.to_bb();
+        side = !side;
+    }
+
+    // either side may decline to recapture, so each step keeps the better of taking or stopping
+    while gains.len() > 1 {
+        let last = gains.pop().unwrap();
+        let previous = gains.len() - 1;
+        gains[previous] = -(-gains[previous]).max(last);
+    }
+    gains[0]
+}
+
+// captures only, most valuable victim first and least valuable attacker among equal victims.
+// pleco can't generate captures alone while in check, use gen_and_order_moves there
+fn gen_and_order_captures(board:&Board) -> MoveList {
+    let mut captures = board.generate_moves_of_type(GenTypes::Captures);
+
+    captures.sort_by_key(|&mv| -(board.captured_piece(mv) as i32 * 8 - board.moved_piece(mv).type_of() as i32));
+
+    captures
+}
+
+fn quiescence(engine:&mut Engine, board:&mut Board, mut alpha:i32, mut beta:i32) -> i32 {
+    (*engine).count_qnode();
+
+    let stand_pat = evaluate(engine, board);
+
+    if (*engine).past_max_plies(board) {
+        return stand_pat;
+    }
+
+    // in check doing nothing isn't an option, standing pat would hide a lost position
+    let in_check = board.in_check();
+
+    if !in_check && board.turn() == Player::White {
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        if alpha < stand_pat {
+            alpha = stand_pat;
+        }
+    }
+    else if !in_check {
+        if stand_pat <= alpha {
+            return stand_pat;
+        }
+        if stand_pat < beta {
+            beta = stand_pat;
+        }
+    }
+
+    // in check every evasion has to be tried, quiet king moves and blocks included
+    let moves = {
+        if in_check { gen_and_order_moves(engine, board, BitMove::null()) } else { gen_and_order_captures(board) }
+    };
+
+    // no evasion at all, `evaluate` already scored the mate
+    if in_check && moves.is_empty() {
+        return stand_pat;
+    }
+
+    for mv in moves {
+        // a capture that loses material can't improve on standing pat. taking something worth
+        // at least the capturing piece never does, those skip the exchange evaluation
+        let victim = (*engine).piece_values[board.captured_piece(mv) as usize];
+        let attacker = (*engine).piece_values[board.moved_piece(mv).type_of() as usize];
+        if !in_check && victim < attacker && see(engine, board, mv) < 0 {
+            continue;
+        }
+
+        (*engine).make_move(board, mv);
+        let score = quiescence(engine, board, alpha, beta);
+        (*engine).unmake_move(board);
+
+        if board.turn() == Player::White {
+            if alpha < score {
+                alpha = score;
+            }
+        }
+        else if score < beta {
+            beta = score;
+        }
+
+        if beta <= alpha {
+            break;
+        }
+    }
+
+    if board.turn() == Player::White { alpha } else { beta }
+}
+
+// a capture near the leaves that clearly loses material in the exchange isn't worth its subtree.
+// captures that give check are kept, the sacrifices worth searching are among them
+fn losing_capture(engine:&Engine, board:&Board, mv:BitMove, depth:u8) -> bool {
+    if depth > SEE_PRUNE_DEPTH || !mv.is_capture() || board.in_check() || board.gives_check(mv) {
+        return false;
+    }
+    let victim = (*engine).piece_values[board.captured_piece(mv) as usize];
+    let attacker = (*engine).piece_values[board.moved_piece(mv).type_of() as usize];
+    victim < attacker && see(engine, board, mv) < -SEE_PRUNE_MARGIN * depth as i32
+}
+
+// `is_pv` for the root and the first move of a PV node, zero window searches never are
+#[allow(clippy::too_many_arguments)]
+fn minimax(engine:&mut Engine, board:&mut Board, depth:u8, mut alpha:i32, mut beta:i32, search_extensions: u8, prev_move:BitMove, is_pv:bool) -> (BitMove, i32) {
+    if depth == 0 {
+        return (BitMove::null(), quiescence(engine, board, alpha, beta));
+    }
+
+    // a line that crosses the fifty-move limit is drawn there, whatever the table says about the position.
+    // `evaluate` still finds a mate delivered on the last move
+    if (*engine).past_max_plies(board) || ((*engine).ply(board) != 0 && is_fifty_move_draw(board)) {
+        (*engine).count_node();
+        return (BitMove::null(), evaluate(engine, board));
+    }
+
+    // `DrawScore` and `DrawOffset` apply to a repetition just like to any other draw
+    if (*engine).ply(board) != 0 && (*engine).repeats(board) {
+        (*engine).count_node();
+        return (BitMove::null(), (*engine).draw_score(board));
+    }
+
+    (*engine).count_node();
+
+    let mut moves = {
+        if (*engine).ply(board) == 0 { gen_and_order_root_moves(engine, board, prev_move) } else { gen_and_order_moves(engine, board, prev_move) }
+    }; // gen moves and order
+    if moves.is_empty() {
+        return (BitMove::null(), evaluate(engine, board));
+    }
+
+    // a root restricted by `go searchmoves` or MultiPV neither uses nor pollutes the table
+    let restricted_root = (*engine).ply(board) == 0 && !(*engine).root_moves.is_empty();
+    if restricted_root {
+        moves = moves.iter().filter(|mv| (*engine).root_moves.contains(mv)).copied().collect();
+    }
+    // and neither does a search cut short by `go maxmoves`
+    let use_table = !restricted_root && (*engine).max_plies == 0;
+
+    let possible_transposition = (*engine).transposition_find(board);
+    if possible_transposition.best_move != BitMove::null() && use_table {
+
+        if possible_transposition.depth >= depth {
+            let usable = match possible_transposition.bound {
+                BOUND_LOWER => possible_transposition.score >= beta,
+                BOUND_UPPER => possible_transposition.score <= alpha,
+                _ => true,
+            };
+            if usable {
+                return (possible_transposition.best_move, possible_transposition.score);
+            }
+        }
+
+        // no cutoff, but the table's move is still the best guess here
+        table_move_first(&mut moves, possible_transposition.best_move);
+    }
+
+
+    if (*engine).out_of_time() {
+        return 
+            if board.turn() == Player::White 
+            {(BitMove::null(),-1)} 
+            else 
+            {(BitMove::null(),-1)}
+    }
+
+    let mut best_move = BitMove::null();
+
+    let mut quiets_tried: Vec<BitMove> = Vec::new();
+    let (original_alpha, original_beta) = (alpha, beta);
+    // (move, score) of every root move searched, for the next iteration's order
+    let mut root_scores: Vec<(BitMove, i32)> = Vec::new();
+
+    if board.turn() == Player::White {
+        for (move_index, &mv) in moves.iter().enumerate() {
+            // never the first move, nor at the root
+            if move_index > 0 && (*engine).ply(board) != 0 && losing_capture(engine, board, mv, depth) {
+                continue;
+            }
+            let reduction = late_move_reduction(engine, board, mv, depth, move_index, is_pv);
+            (*engine).make_move(board, mv);
+            let eval = {
+                if search_extensions < MAX_EXTENSIONS && extends(engine, board, mv, prev_move, is_pv) {
+                    search_move(engine, board, depth, alpha, beta, search_extensions + 1, mv, 0, is_pv, move_index == 0)
+                }
+                else if depth <= (*engine).futility_depth && futile(engine, board, depth, alpha, beta) {
+                    (mv, alpha - 2)
+                }
+                else {
+                    search_move(engine, board, depth - 1, alpha, beta, search_extensions, mv, reduction, is_pv, move_index == 0)
+                }
+            };
+            (*engine).unmake_move(board);
+
+            // the subtree was cut short, (null, -1) could also be a real leaf score
+            if (*engine).out_of_time() {
+                return (BitMove::null(),-1);
+            }
+
+            if (*engine).ply(board) == 0 {
+                root_scores.push((mv, eval.1));
+            }
+
+            if alpha < eval.1 {
+                alpha = eval.1;
+                best_move = mv;
+            }
+            
+            if beta <= alpha {
+                if is_quiet(mv) {
+                    (*engine).update_history(board, mv, &quiets_tried, depth);
+                    (*engine).store_countermove(board, prev_move, mv);
+                }
+                break;
+            }
+
+            if is_quiet(mv) {
+                quiets_tried.push(mv);
+            }
+        }
+        let bound = {
+            if beta <= alpha { BOUND_LOWER } else if alpha > original_alpha { BOUND_EXACT } else { BOUND_UPPER }
+        };
+        if use_table {
+            (*engine).transposition_store(board, alpha, best_move, depth, bound);
+        }
+        if (*engine).ply(board) == 0 && !restricted_root {
+            (*engine).store_root_order(board, &moves, root_scores, best_move);
+        }
+        return (best_move,alpha)
+    }
+    else {
+        for (move_index, &mv) in moves.iter().enumerate() {
+            // never the first move, nor at the root
+            if move_index > 0 && (*engine).ply(board) != 0 && losing_capture(engine, board, mv, depth) {
+                continue;
+            }
+            let reduction = late_move_reduction(engine, board, mv, depth, move_index, is_pv);
+            (*engine).make_move(board, mv);
+            let eval = {
+                if search_extensions < MAX_EXTENSIONS && extends(engine, board, mv, prev_move, is_pv) {
+                    search_move(engine, board, depth, alpha, beta, search_extensions + 1, mv, 0, is_pv, move_index == 0)
+                }
+                else if depth <= (*engine).futility_depth && futile(engine, board, depth, alpha, beta) {
+                    (mv, beta + 2)
+                }
+                else {
+                    search_move(engine, board, depth - 1, alpha, beta, search_extensions, mv, reduction, is_pv, move_index == 0)
+                }
+            };
+            (*engine).unmake_move(board);
+
+            // the subtree was cut short, (null, -1) could also be a real leaf score
+            if (*engine).out_of_time() {
+                return (BitMove::null(),-1);
+            }
+
+            if (*engine).ply(board) == 0 {
+                root_scores.push((mv, eval.1));
+            }
+
+            if eval.1 < beta {
+                beta = eval.1;
+                best_move = mv;
+            }
+            if beta <= alpha {
+                if is_quiet(mv) {
+                    (*engine).update_history(board, mv, &quiets_tried, depth);
+                    (*engine).store_countermove(board, prev_move, mv);
+                }
+                break;
+            }
+
+            if is_quiet(mv) {
+                quiets_tried.push(mv);
+            }
+        }
+        let bound = {
+            if beta <= alpha { BOUND_UPPER } else if beta < original_beta { BOUND_EXACT } else { BOUND_LOWER }
+        };
+        if use_table {
+            (*engine).transposition_store(board, beta, best_move, depth, bound);
+        }
+        if (*engine).ply(board) == 0 && !restricted_root {
+            (*engine).store_root_order(board, &moves, root_scores, best_move);
+        }
+        return (best_move,beta)
+    }
+}
+
+// principal variation search for a move already made on `board`: the first move of a node gets the
+// node's window and, on a PV node, stays on the PV. a later move only has to show it can't beat the
+// best so far, with a zero window and LMR's reduction, and is searched again at full depth when it
+// might, on a PV node with the full window too
+#[allow(clippy::too_many_arguments)]
+fn search_move(engine:&mut Engine, board:&mut Board, depth:u8, alpha:i32, beta:i32, search_extensions:u8, mv:BitMove, reduction:u8, is_pv:bool, first:bool) -> (BitMove, i32) {
+    if first {
+        return minimax(engine, board, depth, alpha, beta, search_extensions, mv, is_pv);
+    }
+
+    // the side that played `mv`
+    let white = board.turn() == Player::Black;
+    let (zero_alpha, zero_beta) = { if white { (alpha, alpha.saturating_add(1)) } else { (beta.saturating_sub(1), beta) } };
+    let beats = |score:i32| { if white { alpha < score } else { score < beta } };
+
+    let mut eval = minimax(engine, board, depth - reduction, zero_alpha, zero_beta, search_extensions, mv, false);
+    if reduction > 0 && beats(eval.1) {
+        eval = minimax(engine, board, depth, zero_alpha, zero_beta, search_extensions, mv, false);
+    }
+    if is_pv && beats(eval.1) {
+        eval = minimax(engine, board, depth, alpha, beta, search_extensions, mv, true);
+    }
+    eval
+}
+
+// Plays random legal moves from the current position, after each one checking what pleco and the
+// pawn hash keep incrementally (keys, cached pawn structure, evaluation) against a board rebuilt
+// from its FEN, and that a null move and its undo leave the board as it was.
+// Returns how many moves were checked, or the first mismatch
+fn validate(engine:&mut Engine, moves:usize) -> Result<usize, String> {
+    let mut board = (*engine).board.shallow_clone();
+    let mut rng = rand::thread_rng();
+    let mut played: Vec<String> = Vec::new();
+
+    for _ in 0..moves {
+        let legal_moves = board.generate_moves();
+        if legal_moves.is_empty() {
+            break;
+        }
+        let mv = legal_moves[rng.gen_range(0..legal_moves.len())];
+        board.apply_move(mv);
+        played.push(mv.to_string());
+
+        let fen = board.fen();
+        let fresh = Board::from_fen(&fen).map_err(|_| format!("after {}: {} does not parse", played.join(" "), fen))?;
+
+        // same key, so the fresh board is evaluated without the cache
+        let evaluation = evaluate(engine, &board);
+        (*engine).clear_eval_cache();
+        let fresh_evaluation = evaluate(engine, &fresh);
+
+        let checks = [
+            ("zobrist key", position_key(&board) as i64, position_key(&fresh) as i64),
+            ("pawn key", board.pawn_key() as i64, fresh.pawn_key() as i64),
+            ("pawn structure", (*engine).pawn_structure(&board) as i64, pawn_structure(&fresh) as i64),
+            ("evaluation", evaluation as i64, fresh_evaluation as i64),
+        ];
+        for (name, incremental, full) in checks {
+            if incremental != full {
+                return Err(format!("after {}: {} {} but {} from scratch ({})", played.join(" "), name, incremental, full, fen));
+            }
+        }
+
+        if !board.in_check() {
+            let key = position_key(&board);
+            let ply = board.ply();
+            let accumulators = (*engine).accumulators.len();
+            (*engine).make_null_move(&mut board);
+            let null_fen = board.fen();
+            let null_fresh = Board::from_fen(&null_fen).map_err(|_| format!("after {} and a null move: {} does not parse", played.join(" "), null_fen))?;
+            let null_key = position_key(&board);
+            (*engine).unmake_null_move(&mut board);
+
+            let null_checks = [
+                ("zobrist key after a null move", null_key as i64, position_key(&null_fresh) as i64),
+                ("zobrist key after a null move and its undo", position_key(&board) as i64, key as i64),
+                ("ply after a null move and its undo", board.ply() as i64, ply as i64),
+                ("accumulators after a null move and its undo", (*engine).accumulators.len() as i64, accumulators as i64),
+            ];
+            for (name, incremental, full) in null_checks {
+                if incremental != full {
+                    return Err(format!("after {}: {} {} but {} expected ({})", played.join(" "), name, incremental, full, fen));
+                }
+            }
+            if board.fen() != fen {
+                return Err(format!("after {}: a null move and its undo left {} instead of {}", played.join(" "), board.fen(), fen));
+            }
+        }
+    }
+
+    Ok(played.len())
+}
+
+// Lazy SMP: a helper searches the same root as the main thread, every other helper one ply
+// ahead, and only contributes through the shared transposition table
+fn helper_search(mut engine:Engine, max_depth:u8) {
+    let mut shallow_board = engine.board.shallow_clone();
+    let last_move = engine.board.last_move().unwrap_or(BitMove::null());
+
+    let mut depth = (engine.thread_index % 2) as u8;
+
+    while !engine.out_of_time() && depth < max_depth {
+        depth += 1;
+        minimax(&mut engine, &mut shallow_board, depth, MINIMUM_EVAL, MAXIMUM_EVAL, 0, last_move, true);
+    }
+}
+
+// one root move the way `minimax` searches it: reduced when late, pruned when futile near the horizon,
+// with a zero window after the first
+fn search_root_move(engine:&mut Engine, board:&mut Board, mv:BitMove, move_index:usize, depth:u8, alpha:i32, beta:i32) -> i32 {
+    let white = board.turn() == Player::White;
+    let reduction = late_move_reduction(engine, board, mv, depth, move_index, true);
+    (*engine).make_move(board, mv);
+    let score = {
+        if depth <= (*engine).futility_depth && futile(engine, board, depth, alpha, beta) {
+            if white { alpha - 2 } else { beta + 2 }
+        }
+        else {
+            search_move(engine, board, depth - 1, alpha, beta, 0, mv, reduction, true, move_index == 0).1
+        }
+    };
+    (*engine).unmake_move(board);
+    score
+}
+
+fn root_split_worker(engine:&mut Engine, board:&mut Board, moves:&[BitMove], best:&Mutex<(BitMove, i32)>, next_move:&AtomicUsize, depth:u8) {
+    let white = board.turn() == Player::White;
+
+    loop {
+        let index = next_move.fetch_add(1, Ordering::Relaxed);
+        if index >= moves.len() || (*engine).out_of_time() {
+            break;
+        }
+
+        let bound = best.lock().unwrap().1;
+        let (alpha, beta) = { if white { (bound, MAXIMUM_EVAL) } else { (MINIMUM_EVAL, bound) } };
+
+        let score = search_root_move(engine, board, moves[index], index, depth, alpha, beta);
+        if (*engine).out_of_time() {
+            break;
+        }
+
+        let mut best = best.lock().unwrap();
+        if (white && score > best.1) || (!white && score < best.1) {
+            *best = (moves[index], score);
+        }
+    }
+}
+
+// the first root move is searched alone to get a bound, the rest go to whichever thread is free next,
+// each searched against the best score found so far. a move only replaces the best on a strictly
+// better score, so apart from ties the result doesn't depend on which thread finishes first
+fn root_split_search(engine:&mut Engine, board:&mut Board, depth:u8, prev_move:BitMove) -> (BitMove, i32) {
+    let mut moves = gen_and_order_moves(engine, board, prev_move);
+    if !(*engine).root_moves.is_empty() {
+        moves = moves.iter().filter(|mv| (*engine).root_moves.contains(mv)).copied().collect();
+    }
+
+    if depth == 1 || moves.len() < 2 {
+        return minimax(engine, board, depth, MINIMUM_EVAL, MAXIMUM_EVAL, 0, prev_move, true);
+    }
+
+    (*engine).count_node();
+
+    let first = search_root_move(engine, board, moves[0], 0, depth, MINIMUM_EVAL, MAXIMUM_EVAL);
+    if (*engine).out_of_time() {
+        return (BitMove::null(), -1);
+    }
+
+    let moves = Arc::new(moves);
+    let best = Arc::new(Mutex::new((moves[0], first)));
+    let next_move = Arc::new(AtomicUsize::new(1));
+
+    // the main thread takes moves too, on its own engine so its history carries over to the next iteration
+    let workers: Vec<Job> = (1..(*engine).threads).map(|thread_index| {
+        let mut worker = (*engine).clone();
+        worker.thread_index = thread_index;
+        let mut board = board.shallow_clone();
+        let (moves, best, next_move) = (Arc::clone(&moves), Arc::clone(&best), Arc::clone(&next_move));
+        Box::new(move || root_split_worker(&mut worker, &mut board, &moves, &best, &next_move, depth)) as Job
+    }).collect();
+    let workers = (*engine).pool.start(workers);
+    root_split_worker(engine, board, &moves, &best, &next_move, depth);
+    (*engine).pool.wait(workers);
+
+    if (*engine).out_of_time() {
+        return (BitMove::null(), -1);
+    }
+
+    let best = *best.lock().unwrap();
+    if (*engine).root_moves.is_empty() && (*engine).max_plies == 0 {
+        (*engine).transposition_store(board, best.1, best.0, depth, BOUND_EXACT);
+    }
+    best
+}
+
+// searches the root in a window around the previous iteration's score, widening whichever side
+// the result fell outside of until it lands inside
+fn aspiration_search(engine:&mut Engine, board:&mut Board, depth:u8, previous_score:i32, prev_move:BitMove) -> (BitMove, i32) {
+    let mut window = (*engine).aspiration_window;
+    if window == 0 || depth == 1 {
+        return minimax(engine, board, depth, MINIMUM_EVAL, MAXIMUM_EVAL, 0, prev_move, true);
+    }
+
+    let mut alpha = previous_score.saturating_sub(window).max(MINIMUM_EVAL);
+    let mut beta = previous_score.saturating_add(window);
+
+    loop {
+        let result = minimax(engine, board, depth, alpha, beta, 0, prev_move, true);
+
+        if (*engine).out_of_time() {
+            return result;
+        }
+
+        window = window.saturating_mul(2);
+        let white = board.turn() == Player::White;
+        if result.1 <= alpha && alpha > MINIMUM_EVAL {
+            alpha = previous_score.saturating_sub(window).max(MINIMUM_EVAL);
+            if white { (*engine).aspiration_fail_lows += 1 } else { (*engine).aspiration_fail_highs += 1 }
+        }
+        else if result.1 >= beta && beta < MAXIMUM_EVAL {
+            beta = previous_score.saturating_add(window);
+            if white { (*engine).aspiration_fail_highs += 1 } else { (*engine).aspiration_fail_lows += 1 }
+        }
+        else {
+            return result;
+        }
+    }
+}
+
+// (win, draw, loss) permilles for a cp score from the side to move's point of view
+fn wdl(score:i32) -> (i32, i32, i32) {
+    let chance = |cp:f64| 1.0 / (1.0 + f64::exp(-(cp - WDL_DRAW_MARGIN) / WDL_SCALE));
+    let win = (chance(score as f64) * 1000.0).round() as i32;
+    let loss = (chance(-score as f64) * 1000.0).round() as i32;
+    (win, 1000 - win - loss, loss)
+}
+
+// the ` wdl W D L` part of an info line when `UCI_ShowWDL` is on
+fn wdl_text(engine:&Engine, score:i32) -> String {
+    if !engine.show_wdl {
+        return String::new();
+    }
+    let (win, draw, loss) = wdl(score);
+    format!(" wdl {} {} {}", win, draw, loss)
+}
+
+// the move a weakened engine plays from the root lines of its last iteration, each line's score
+// (side to move's point of view) is pushed up by a random amount that grows as the level drops
+fn pick_skill_move(engine:&Engine, lines:&[(BitMove, i32)], perspective:i32) -> BitMove {
+    let noise = (MAX_SKILL_LEVEL - engine.skill_level) as i32 * SKILL_NOISE_PER_LEVEL;
+    let mut rng = rand::thread_rng();
+
+    lines.iter()
+        .max_by_key(|line| line.1 * perspective + rng.gen_range(0..=noise))
+        .map(|line| line.0)
+        .unwrap_or(BitMove::null())
+}
+
+// with `Opening Variety`, a random one of the root lines of the last iteration scoring within `opening_variety`
+// of the best. the lines' scores are exact (side to move's point of view), decisive ones keep the best move
+fn pick_variety_move(engine:&Engine, lines:&[(BitMove, i32)], perspective:i32) -> BitMove {
+    let best = lines.iter().map(|line| line.1 * perspective).max().unwrap_or(0);
+    if best.abs() > OPENING_VARIETY_SCORE_LIMIT {
+        return lines.iter().find(|line| line.1 * perspective == best).map(|line| line.0).unwrap_or(BitMove::null());
+    }
+    let close: Vec<BitMove> = lines.iter().filter(|line| line.1 * perspective >= best - engine.opening_variety).map(|line| line.0).collect();
+    if close.is_empty() {
+        return BitMove::null();
+    }
+    close[rand::thread_rng().gen_range(0..close.len())]
+}
+
+// follows the table's best moves from the root move, at most `depth` plies.
+// entries can point at each other around a repetition, so a position seen twice ends the line
+fn principal_variation(engine:&Engine, board:&Board, root_move:BitMove, depth:u8) -> Vec<BitMove> {
+    let mut board = board.shallow_clone();
+    let mut pv = Vec::new();
+    let mut visited = vec![position_key(&board)];
+    let mut mv = root_move;
+
+    while !mv.is_null() && pv.len() < depth as usize && board.generate_moves().contains(&mv) {
+        board.apply_move(mv);
+        pv.push(mv);
+
+        let key = position_key(&board);
+        if visited.contains(&key) {
+            break;
+        }
+        visited.push(key);
+
+        mv = engine.transposition_find(&mut board).best_move;
+    }
+
+    pv
+}
+
+// best score first, equal scores in the order of the last iteration so the lines don't swap places between depths.
+// lines new to this iteration go after the ones already ranked, in the order they were found
+fn rank_root_lines(lines:&mut [(BitMove, i32)], previous_ranking:&[BitMove], perspective:i32) {
+    lines.sort_by_key(|&(mv, score)| {
+        (-(score * perspective), previous_ranking.iter().position(|&ranked| ranked == mv).unwrap_or(usize::MAX))
+    });
+}
+
+// the `bestmove` line, with the reply we expect as the move to ponder on when the PV has one
+fn bestmove_text(best_move:BitMove, pv:&[BitMove]) -> String {
+    match pv.get(1) {
+        Some(reply) => format!("bestmove {} ponder {}", best_move, reply),
+        None => format!("bestmove {}", best_move),
+    }
+}
+
+fn search(engine:&mut Engine) -> BitMove {
+
+    let mut shallow_board = (*engine).board.shallow_clone();
+    
+    let mut depth = 0;
+
+    let mut best_move_info: (BitMove, i32) = (BitMove::null(), 0);
+
+    (*engine).instant = Instant::now();
+    (*engine).generation = ((*engine).generation + 1) & GENERATION_MASK;
+    (*engine).reset_nodes();
+    (*engine).aspiration_fail_highs = 0;
+    (*engine).aspiration_fail_lows = 0;
+    (*engine).eval_cache_probes = 0;
+    (*engine).eval_cache_hits = 0;
+    (*engine).start_line();
+    (*engine).node_limit = u64::MAX;
+
+    let perspective = {
+        if (*engine).board.turn() == Player::White {1} else {-1}
+    };
+
+    let last_move = (*engine).board.last_move().unwrap_or(BitMove::null());
+
+    (*engine).pv.clear();
+    (*engine).pv_lines.clear();
+    (*engine).pv_root_key = position_key(&(*engine).board);
+
+    // the game is already over, there is no move to send
+    if let Some((score, reason)) = game_over(&(*engine).board) {
+        if !(*engine).quiet {
+            println!("info depth 0 score {}", score);
+            info_string(&format!("{}, no legal moves", reason));
+            println!("bestmove (none)");
+        }
+        return BitMove::null();
+    }
+
+    (*engine).log_search(&format!("search fen={}", (*engine).board.fen()));
+
+    // with a single legal reply there is nothing to think about, one ply is enough for the info line
+    let max_depth = {
+        let root_moves = {
+            if (*engine).search_moves.is_empty() { (*engine).board.generate_moves().len() } else { (*engine).search_moves.len() }
+        };
+        if root_moves == 1 && !(*engine).analyse_mode { 1 }
+        else if (*engine).skill_level < MAX_SKILL_LEVEL { (*engine).depth.min((*engine).max_depth).min(1 + (*engine).skill_level) }
+        else { (*engine).depth.min((*engine).max_depth) }
+    };
+
+    (*engine).search_finished.store(false, Ordering::Relaxed);
+    (*engine).root_moves = (*engine).search_moves.clone();
+    // root split workers are started per iteration instead
+    let helper_count = { if (*engine).thread_mode == ThreadMode::LazySmp { (*engine).threads } else { 1 } };
+    let helpers: Vec<Job> = (1..helper_count).map(|thread_index| {
+        let mut helper = (*engine).clone();
+        helper.thread_index = thread_index;
+        Box::new(move || helper_search(helper, max_depth)) as Job
+    }).collect();
+    let helpers = (*engine).pool.start(helpers);
+
+    // MultiPV lines are picked from the searchmoves when there are any
+    let candidates = {
+        if (*engine).search_moves.is_empty() { (*engine).board.generate_moves().vec() } else { (*engine).search_moves.clone() }
+    };
+    // both pick from several exactly scored lines
+    let skill_lines = {
+        if (*engine).skill_level < MAX_SKILL_LEVEL || (*engine).opening_variety_active(&(*engine).board) { SKILL_LINES } else { 1 }
+    };
+    let lines = (*engine).multipv.max(skill_lines).min(candidates.len());
+    // (move, score) of every line of the last iteration that finished its first line
+    let mut root_lines: Vec<(BitMove, i32)> = Vec::new();
+    // the moves of those lines in the order they were shown, lines with equal scores keep it
+    let mut previous_ranking: Vec<BitMove> = Vec::new();
+    // nodes of the last finished iteration, MultiPV lines left out, for the effective branching factor
+    let mut previous_iteration_nodes: u64 = 0;
+    // iterations in a row that scored the root as a draw, see STABLE_DRAW_ITERATIONS
+    let mut draw_iterations: u32 = 0;
+
+    (*engine).first_iteration = true;
+    while !(*engine).out_of_time() && depth < max_depth {
+        let past_best_move_info = best_move_info;
+        let iteration_start_nodes = (*engine).total_nodes();
+
+        depth += 1;
+
+        (*engine).root_moves = (*engine).search_moves.clone();
+        best_move_info = {
+            if (*engine).thread_mode == ThreadMode::RootSplit && (*engine).threads > 1 {
+                root_split_search(engine, &mut shallow_board, depth, last_move)
+            } else {
+                aspiration_search(
+                    engine,
+                    &mut shallow_board, 
+                    depth, 
+                    past_best_move_info.1,
+                    last_move,
+                )
+            }
+        };
+
+        // an interrupted iteration proves nothing, the last completed one stands
+        if (*engine).out_of_time() {
+            best_move_info = past_best_move_info;
+            break;
+        }
+        (*engine).first_iteration = false;
+
+        // how many times the nodes of the previous iteration this one took, lower means better ordering and pruning
+        let iteration_nodes = (*engine).total_nodes() - iteration_start_nodes;
+        if (*engine).debug && previous_iteration_nodes != 0 {
+            info_string(&format!("depth {} ebf {:.2}", depth, iteration_nodes as f64 / previous_iteration_nodes as f64));
+        }
+        previous_iteration_nodes = iteration_nodes;
+
+        // every further line is searched with the moves of the lines above it excluded
+        root_lines = vec![best_move_info];
+        let mut shown = vec![best_move_info.0];
+        for _ in 2..=lines {
+            (*engine).root_moves = candidates.iter().filter(|mv| !shown.contains(mv)).copied().collect();
+            let line_info = minimax(engine, &mut shallow_board, depth, MINIMUM_EVAL, MAXIMUM_EVAL, 0, last_move, true);
+            if (*engine).out_of_time() || line_info.0.is_null() {
+                break;
+            }
+            shown.push(line_info.0);
+            root_lines.push(line_info);
+        }
+
+        rank_root_lines(&mut root_lines, &previous_ranking, perspective);
+        previous_ranking = root_lines.iter().map(|&(mv, _)| mv).collect();
+        (*engine).pv_lines = root_lines.clone();
+        best_move_info = root_lines[0];
+
+        (*engine).pv = principal_variation(engine, &shallow_board, best_move_info.0, depth);
+        (*engine).pv_score = best_move_info.1 * perspective;
+        let pv = (*engine).pv.iter().map(|mv| mv.to_string()).collect::<Vec<String>>().join(" ");
+
+        let elapsed = (*engine).elapsed();
+        let nodes = (*engine).total_nodes();
+        let multipv = { if (*engine).multipv > 1 && lines > 1 { " multipv 1" } else { "" } };
+        if !(*engine).quiet {
+            println!("info depth {depth}{multipv} time {} nodes {} nps {} score cp {}{} pv {}", elapsed, nodes, nodes as u128 * 1000 / elapsed.max(1), best_move_info.1 * perspective, wdl_text(engine, best_move_info.1 * perspective), pv);
+        }
+        (*engine).log_search(&format!("depth={} time={} nodes={} score={} best={} pv={}", depth, elapsed, nodes, best_move_info.1 * perspective, best_move_info.0, pv));
+
+        // the extra lines of a weakened engine are only for picking its move
+        for (index, line_info) in root_lines.iter().enumerate().skip(1).take((*engine).multipv.saturating_sub(1)).filter(|_| !(*engine).quiet) {
+            println!("info depth {depth} multipv {} score cp {}{} pv {}", index + 1, line_info.1 * perspective, wdl_text(engine, line_info.1 * perspective), line_info.0);
+        }
+
+        // the clock is only read between nodes, so a pathological subtree could overrun it
+        if elapsed > 0 && !(*engine).pondering.load(Ordering::Relaxed) {
+            let own_nodes = (*engine).nodes[0].load(Ordering::Relaxed) as u128;
+            let remaining = ((*engine).movetime as u128).saturating_sub(elapsed);
+            let node_limit = own_nodes + own_nodes * remaining / elapsed * NODE_LIMIT_FACTOR;
+            (*engine).node_limit = node_limit.min(u64::MAX as u128) as u64;
+        }
+
+        if depth > 1 {
+            (*engine).extend_on_score_drop(past_best_move_info.1 * perspective, best_move_info.1 * perspective);
+        }
+
+        // a low material position that keeps coming out as a draw is most likely a fortress, deeper
+        // iterations would only spend the clock confirming it
+        let draw = (*engine).draw_score(&(*engine).board);
+        if (best_move_info.1 - draw).abs() <= STABLE_DRAW_BAND && game_phase(&(*engine).board) <= ENDGAME_PHASE {
+            draw_iterations += 1;
+        } else {
+            draw_iterations = 0;
+        }
+        if (*engine).stable_draw && draw_iterations >= STABLE_DRAW_ITERATIONS && depth >= STABLE_DRAW_MIN_DEPTH && !(*engine).analyse_mode && !(*engine).pondering.load(Ordering::Relaxed) {
+            if (*engine).debug {
+                info_string(&format!("depth {} scored a draw {} iterations in a row, stopping", depth, draw_iterations));
+            }
+            break;
+        }
+
+        // past the target only keep going while the best move is still changing
+        if !(*engine).analyse_mode && !(*engine).pondering.load(Ordering::Relaxed) && (*engine).elapsed() >= (*engine).target_time.into() && best_move_info.0 == past_best_move_info.0 {
+            break;
+        }
+        
+        //DEBUG (transposition table)
+        //println!("debug-transposition table filled: {} MB/{}.0 MB", (*engine).entries_filled as f64 / MB_TO_ITEMS as f64,((*engine).hash_table_size_mb));
+
+    }
+
+    // a ponder search must not answer before `ponderhit` or `stop`
+    while (*engine).pondering.load(Ordering::Relaxed) && !(*engine).search_stopped.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    (*engine).search_finished.store(true, Ordering::Relaxed);
+    (*engine).pool.wait(helpers);
+
+    if (*engine).debug {
+        info_string(&format!("nodes main {} quiescence {}", (*engine).total_nodes() - (*engine).total_qnodes(), (*engine).total_qnodes()));
+        info_string(&format!("aspiration fail high {} fail low {}", (*engine).aspiration_fail_highs, (*engine).aspiration_fail_lows));
+        info_string(&format!("eval cache hits {} of {} probes", (*engine).eval_cache_hits, (*engine).eval_cache_probes));
+        if (*engine).threads > 1 {
+            // the pool threads are the same from one search to the next until `Threads` changes
+            for (thread_index, count) in (*engine).nodes.iter().enumerate() {
+                let id = { if thread_index == 0 { thread::current().id() } else { (*engine).pool.thread_ids[thread_index - 1] } };
+                info_string(&format!("thread {} {:?} nodes {}", thread_index, id, count.load(Ordering::Relaxed)));
+            }
+        }
+    }
+
+    (*engine).first_iteration = false;
+
+    // the first iteration always finishes, so only something going wrong gets here. the table's move
+    // or else the best ordered one still beats sending a null move
+    if !candidates.contains(&best_move_info.0) {
+        let tt_move = (*engine).transposition_find(&mut shallow_board).best_move;
+        let fallback = {
+            if candidates.contains(&tt_move) { tt_move }
+            else {
+                gen_and_order_root_moves(engine, &mut shallow_board, last_move).iter()
+                    .copied()
+                    .find(|mv| candidates.contains(mv))
+                    .unwrap_or(candidates[0])
+            }
+        };
+        info_string(&format!("search found no usable move, playing {}", fallback));
+        best_move_info.0 = fallback;
+    }
+
+    if (*engine).skill_level < MAX_SKILL_LEVEL && root_lines.len() > 1 {
+        best_move_info.0 = pick_skill_move(engine, &root_lines, perspective);
+    }
+    else if (*engine).opening_variety_active(&(*engine).board) && root_lines.len() > 1 {
+        best_move_info.0 = pick_variety_move(engine, &root_lines, perspective);
+    }
+
+    // the search scores any repetition as a draw already, but only a threefold in the actual game is
+    // a draw we can claim, worth taking when the best move scores worse than that
+    if let Some(repetition) = threefold_move(&(*engine).board, &candidates) {
+        let draw = (*engine).draw_score(&(*engine).board);
+        if repetition != best_move_info.0 && best_move_info.1 * perspective < draw * perspective {
+            info_string(&format!("{} repeats the position a third time, taking the draw over {} at cp {}", repetition, best_move_info.0, best_move_info.1 * perspective));
+            best_move_info = (repetition, draw);
+            (*engine).pv_score = draw * perspective;
+        }
+    }
+
+    // the predicted reply only holds for the move the line was searched with
+    if (*engine).pv.first() != Some(&best_move_info.0) {
+        (*engine).pv = vec![best_move_info.0];
+    }
+
+    if !(*engine).quiet {
+        println!("{}", bestmove_text(best_move_info.0, &(*engine).pv));
+    }
+    (*engine).log_search(&format!("bestmove={} time={} nodes={}", best_move_info.0, (*engine).elapsed(), (*engine).total_nodes()));
+    (*engine).flush_search_log();
+
+    best_move_info.0
+}
+
+#[allow(unused)]
+fn com(text:&String, engine:&mut Engine) {
+    let split_line = text.trim().split(" ");
+
+    let lvec: Vec<&str> = split_line.collect();
+
+    (*engine).re_initialize();
+
+    match lvec[0] {
+        "position" => {
+            // tokens split on any whitespace, GUIs don't always send single spaces
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let moves_index = words.iter().position(|word| *word == "moves").unwrap_or(words.len());
+
+            match words.get(1).copied().unwrap_or("") {
+
+                // lenient GUIs send `position moves xxxx` meaning the start position
+                "startpos" | "moves" => {
+                    (*engine).board = Board::start_pos();
+                    // "position startpos" or "position startpos moves xxxx xxxx"
+                    if moves_index < words.len() {
+                        apply_uci_moves(&mut (*engine).board, &words[moves_index + 1..]);
+                    }
+                }
+                    
+                "fen" => {
+                    // `position fen <6 fields> [moves ...]`
+                    let fields = &words[2.min(moves_index)..moves_index];
+                    match board_from_fields(fields) {
+                        Some(board) => {
+                            (*engine).board = board;
+                            if moves_index < words.len() {
+                                apply_uci_moves(&mut (*engine).board, &words[moves_index + 1..]);
+                            }
+                        }
+                        None => info_string(&format!("Invalid FEN: {}\n Try `position fen <placement> <side> <castling> <en passant> <halfmove> <fullmove> [moves ...]`", fields.join(" "))),
+                    }
+                }
+
+                _ => {
+                    info_string(&format!("Unknown command: {}", text.trim()))
+                }
+            }
+            
+            
+            
+            
+        }
+        
+        "go" => {
+
+            // a GUI that didn't play our move and the predicted reply itself still has the board we searched.
+            // the board before them is kept for a ponder search that ends without `ponderhit`
+            let mut board_before_ponder = None;
+            if lvec.contains(&"ponder") && (*engine).pv.len() >= 2 && position_key(&(*engine).board) == (*engine).pv_root_key {
+                board_before_ponder = Some((*engine).board.clone());
+                (*engine).board.apply_move((*engine).pv[0]);
+                (*engine).board.apply_move((*engine).pv[1]);
+            }
+
+            // set by every limit that isn't a clock
+            let mut other_limit = lvec.contains(&"infinite");
+
+            // the search still runs without them
+            let unknown = unknown_go_parameters(&lvec);
+            if !unknown.is_empty() {
+                info_string(&format!("Ignoring unknown go parameters: {}", unknown.join(" ")));
+            }
+
+            for i in 1..lvec.len() {
+
+                // a parameter missing its number is reported and otherwise ignored, the search still has to answer
+                let value = lvec.get(i+1).map(|word| word.trim()).unwrap_or("");
+                if NUMERIC_GO_PARAMETERS.contains(&lvec[i]) && value.parse::<u64>().is_err() {
+                    info_string(&format!("Unknown command: {}\n Try `go {} <number>`", text.trim(), lvec[i]));
+                    continue;
+                }
+
+                match lvec[i] {
+
+                    // depth 0 would answer with an unsearched move, so every depth searches at least one ply
+                    "depth" => {
+                        let depth = value.parse::<u64>().unwrap_or_default();
+                        (*engine).depth = depth.clamp(1, MAX_DEPTH_LIMIT as u64) as u8;
+                        if depth != (*engine).depth as u64 {
+                            info_string(&format!("go depth {} is outside 1..{}, using {}", depth, MAX_DEPTH_LIMIT, (*engine).depth));
+                        }
+                        other_limit = true;
+                    }
+
+                    "wtime" => {
+                        (*engine).wtime = value.parse::<u32>().unwrap_or_default();
+                    }
+
+                    "btime" => {
+                        (*engine).btime = value.parse::<u32>().unwrap_or_default();
+                    }
+
+                    "movetime" => {
+                        (*engine).movetime = value.parse::<u32>().unwrap_or_default();
+                    }
+
+                    "nodes" => {
+                        (*engine).max_nodes = value.parse::<u64>().unwrap_or_default();
+                        other_limit = true;
+                    }
+
+                    "winc" => {
+                        (*engine).winc = value.parse::<u32>().unwrap_or_default();
+                    }
+
+                    "binc" => {
+                        (*engine).binc = value.parse::<u32>().unwrap_or_default();
+                    }
+
+                    "movestogo" => {
+                        (*engine).movestogo = value.parse::<u32>().unwrap_or_default();
+                    }
+
+                    // non-standard: `go maxmoves N` searches at most N full moves from the root,
+                    // whatever the depth, extensions and quiescence included
+                    "maxmoves" => {
+                        (*engine).max_plies = value.parse::<u16>().unwrap_or_default().saturating_mul(2);
+                    }
+
+                    "searchmoves" => {
+                        let legal_moves = (*engine).board.generate_moves();
+                        for word in lvec[i+1..].iter().take_while(|word| !GO_PARAMETERS.contains(word)) {
+                            match find_move(&legal_moves, word.trim()) {
+                                Some(mv) => (*engine).search_moves.push(mv),
+                                None => info_string(&format!("Illegal move: {}", word.trim())),
+                            }
+                        }
+                    }
+
+                    _ => continue,
+
+                }
+            }
+
+            let (target_time, maximum_time) = allocate_time(
+                (*engine).board.turn(),
+                (*engine).wtime, (*engine).btime,
+                (*engine).winc, (*engine).binc,
+                (*engine).movestogo, (*engine).movetime,
+                (*engine).move_overhead,
+            );
+            // every limit given applies and the search stops at whichever is reached first:
+            // `depth` is never extended by time left over and `movetime` or the clock never by a
+            // depth not yet reached, `nodes` likewise. only MaxDepth, Skill Level and a single legal
+            // move lower the depth. the default time only stands in when nothing at all limits the search
+            let clock_given = (*engine).movetime != 0 || (*engine).wtime != 0 || (*engine).btime != 0;
+            if !clock_given && other_limit {
+                (*engine).target_time = u32::MAX;
+                (*engine).movetime = u32::MAX;
+            } else {
+                (*engine).target_time = target_time;
+                (*engine).movetime = maximum_time;
+            }
+
+
+            // search_stopped and pondering were already set when the input thread read this line.
+            // after `ponderhit` the allocated time still counts from here
+            search(engine);
+            (*engine).searching.store(false, Ordering::Relaxed);
+
+            // stopped while still pondering: the opponent played something else, the predicted moves never happened
+            if let Some(board) = board_before_ponder {
+                if (*engine).pondering.load(Ordering::Relaxed) {
+                    (*engine).board = board;
+                }
+            }
+        }
+
+        "autoplay" => {
+            let autoplay_depth = {
+                if lvec.len() > 1 { lvec[1].trim().parse::<u8>().unwrap_or(6) } else { 6 }
+            };
+            let previous_depth = (*engine).depth;
+            let mut move_list: Vec<String> = Vec::new();
+
+            let result = loop {
+                if let Some(result) = game_result(&(*engine).board) {
+                    break result;
+                }
+
+                (*engine).depth = autoplay_depth;
+                (*engine).movetime = u32::MAX;
+                (*engine).target_time = u32::MAX;
+                (*engine).search_stopped.store(false, Ordering::Relaxed);
+
+                let best_move = search(engine);
+                if best_move.is_null() || !(*engine).board.apply_uci_move(&best_move.to_string()) {
+                    break "*".to_string();
+                }
+
+                move_list.push(best_move.to_string());
+            };
+
+            (*engine).depth = previous_depth;
+
+            info_string(&format!("autoplay moves {}", move_list.join(" ")));
+            info_string(&format!("autoplay result {}", result));
+        }
+        
+        // `solve <depth>`: a fixed depth search of the current position answered with a single
+        // `solution <bestmove> <score>` line, score in cp for the side to move, for puzzle batch runs
+        "solve" => {
+            let solve_depth = match lvec.get(1).and_then(|word| word.trim().parse::<u8>().ok()) {
+                Some(depth) if depth > 0 => depth,
+                _ => {
+                    info_string(&format!("Unknown command: {}\n Try `solve 8`", text.trim()));
+                    return;
+                }
+            };
+
+            (*engine).depth = solve_depth;
+            (*engine).movetime = u32::MAX;
+            (*engine).target_time = u32::MAX;
+            (*engine).search_stopped.store(false, Ordering::Relaxed);
+            (*engine).quiet = true;
+
+            let best_move = search(engine);
+            (*engine).quiet = false;
+
+            if best_move.is_null() {
+                // checkmate or stalemate, the evaluation already scores both
+                let board = (*engine).board.shallow_clone();
+                let perspective = { if board.turn() == Player::White { 1 } else { -1 } };
+                println!("solution (none) {}", evaluate(engine, &board) * perspective);
+            } else {
+                println!("solution {} {}", best_move, (*engine).pv_score);
+            }
+        }
+
+        "setoption" => {
+            match lvec.get(1).copied().unwrap_or("") {
+                "name" => {
+                    // option names and values may contain spaces, e.g. `setoption name Opening Variety value 8`
+                    let value_index = lvec.iter().position(|word| *word == "value").unwrap_or(lvec.len());
+                    let name = lvec[2..value_index].join(" ");
+
+                    // buttons of other engines, e.g. `setoption name Clear Hash`, have no value
+                    if value_index + 1 >= lvec.len() && !option_values(engine).iter().any(|(option, _)| *option == name) {
+                        info_string(&format!("Ignoring unknown option {}, `uci` lists the supported ones", name));
+                        return;
+                    }
+                    if value_index + 1 >= lvec.len() {
+                        info_string(&format!("Unknown command: {}\n Try `setoption name {} value _`", text.trim(), name));
+                        return;
+                    }
+                    let value = lvec[value_index + 1..].join(" ");
+
+                    // piece values, tables and evaluation terms all come from options
+                    (*engine).clear_eval_cache();
+
+                    match name.as_str() {
+
+                        "Hash" => {
+                            match value.parse::<usize>() {
+                                Ok(size) => {
+                                    let clamped = clamp_hash_mb(size);
+                                    if clamped != size {
+                                        info_string(&format!("Hash {} MB is outside {}..{}, using {} MB", size, MIN_HASH_MB, MAX_HASH_MB, clamped));
+                                    }
+                                    (*engine).change_hash_size(clamped);
+                                    // the request may have been clamped or refused for lack of memory
+                                    info_string(&format!("Hash {} MB allocated, {} entries", (*engine).hash_table_size_mb, (*engine).transposition_table.slots.len()));
+                                }
+                                Err(_) => info_string(&format!("Unknown command: {}\n Try `setoption name Hash value 64`", text.trim())),
+                            }
+                        }
+
+                        "PawnValue" | "KnightValue" | "BishopValue" | "RookValue" | "QueenValue" => {
+                            let piece_index = match name.as_str() {
+                                "PawnValue" => 1,
+                                "KnightValue" => 2,
+                                "BishopValue" => 3,
+                                "RookValue" => 4,
+                                _ => 5,
+                            };
+                            (*engine).piece_values[piece_index] = value.parse().unwrap_or(DEFAULT_PIECE_VALUES[piece_index]);
+                        }
+
+                        "EvalCache" => {
+                            (*engine).resize_eval_cache(value.parse::<usize>().unwrap_or(DEFAULT_EVAL_CACHE_MB).min(MAX_EVAL_CACHE_MB));
+                        }
+
+                        "HashAgeLimit" => {
+                            (*engine).hash_age_limit = value.parse::<u8>().unwrap_or(DEFAULT_HASH_AGE_LIMIT).clamp(1, GENERATION_MASK);
+                        }
+
+                        "NodesTime" => {
+                            (*engine).nodes_time = value.parse::<u64>().unwrap_or_default().min(MAX_NODES_TIME);
+                        }
+
+                        "Move Overhead" => {
+                            (*engine).move_overhead = value.parse::<u32>().unwrap_or(DEFAULT_MOVE_OVERHEAD).min(MAX_MOVE_OVERHEAD);
+                        }
+
+                        "Threads" => {
+                            let requested = value.parse::<usize>().unwrap_or(1);
+                            let cpus = thread::available_parallelism().map(|cpus| cpus.get()).unwrap_or(1).min(MAX_THREADS);
+                            let threads = requested.clamp(1, cpus);
+                            if threads != requested {
+                                info_string(&format!("Threads {} is outside 1..{}, using {}", requested, cpus, threads));
+                            }
+                            (*engine).change_threads(threads);
+                        }
+
+                        "DrawOffset" => {
+                            (*engine).draw_offset = value.parse().unwrap_or_default();
+                        }
+
+                        "MultiPV" => {
+                            (*engine).multipv = value.parse::<usize>().unwrap_or(1).clamp(1, MAX_MULTIPV);
+                        }
+
+                        "MaxDepth" => {
+                            (*engine).max_depth = value.parse::<u8>().unwrap_or(DEFAULT_MAX_DEPTH).clamp(1, MAX_DEPTH_LIMIT);
+                        }
+
+                        "LMRBase" => {
+                            (*engine).lmr_base = value.parse::<f32>().unwrap_or(LMR_BASE);
+                            (*engine).lmr_table = lmr_table((*engine).lmr_base, (*engine).lmr_divisor);
+                        }
+
+                        "LMRDivisor" => {
+                            (*engine).lmr_divisor = value.parse::<f32>().unwrap_or(LMR_DIVISOR).max(LMR_MIN_DIVISOR);
+                            (*engine).lmr_table = lmr_table((*engine).lmr_base, (*engine).lmr_divisor);
+                        }
+
+                        "AspirationWindow" => {
+                            (*engine).aspiration_window = value.parse::<i32>().unwrap_or(DEFAULT_ASPIRATION_WINDOW).clamp(0, MAX_ASPIRATION_WINDOW);
+                        }
+
+                        "Skill Level" => {
+                            (*engine).skill_level = value.parse::<u8>().unwrap_or(MAX_SKILL_LEVEL).min(MAX_SKILL_LEVEL);
+                        }
+
+                        "Ponder" => {
+                            (*engine).ponder = value == "true";
+                        }
+
+                        "UCI_Opponent" => {
+                            // <title> <rating> <computer|human> <name>, e.g. `GM 2800 human Gary Kasparov`
+                            let words: Vec<&str> = value.split(' ').collect();
+                            (*engine).opponent_rating = words.get(1).and_then(|rating| rating.parse().ok());
+                            (*engine).opponent_name = words.get(3..).map(|name| name.join(" ")).unwrap_or_default();
+                        }
+
+                        "Evaluation" => {
+                            match value.as_str() {
+                                "classic" => (*engine).evaluation_mode = EvaluationMode::Classic,
+                                "full" => (*engine).evaluation_mode = EvaluationMode::Full,
+                                _ => info_string(&format!("Unknown command: {}\n Try `setoption name Evaluation value classic` or `full`", text.trim())),
+                            }
+                        }
+
+                        "Thread Mode" => {
+                            match value.as_str() {
+                                "lazysmp" => (*engine).thread_mode = ThreadMode::LazySmp,
+                                "rootsplit" => (*engine).thread_mode = ThreadMode::RootSplit,
+                                _ => info_string(&format!("Unknown command: {}\n Try `setoption name Thread Mode value lazysmp` or `rootsplit`", text.trim())),
+                            }
+                        }
+
+                        "SearchLog" => {
+                            (*engine).search_log = None;
+                            (*engine).search_log_path = value.clone();
+                            if !value.is_empty() && value != "<empty>" {
+                                match OpenOptions::new().create(true).append(true).open(&value) {
+                                    Ok(file) => (*engine).search_log = Some(Arc::new(Mutex::new(BufWriter::new(file)))),
+                                    Err(error) => info_string(&format!("could not open search log {}: {}", value, error)),
+                                }
+                            }
+                        }
+
+                        // either a network (binary, starts with NNUE_MAGIC) or text piece values and tables
+                        "EvalFile" => {
+                            (*engine).network = None;
+                            (*engine).piece_values = DEFAULT_PIECE_VALUES;
+                            (*engine).piece_tables = PIECE_TABLES_ALL;
+                            (*engine).eval_file = value.clone();
+
+                            if value == "<empty>" {
+                                return;
+                            }
+                            match std::fs::read(&value) {
+                                Ok(bytes) if bytes.starts_with(NNUE_MAGIC) => match Network::load(&value) {
+                                    Ok(network) => {
+                                        info_string(&format!("loaded network {} ({} hidden neurons)", value, network.hidden));
+                                        (*engine).network = Some(Arc::new(network));
+                                    }
+                                    Err(error) => info_string(&format!("could not load network {}: {}, using the classical evaluation", value, error)),
+                                },
+                                Ok(bytes) => match load_eval_weights(&String::from_utf8_lossy(&bytes)) {
+                                    Ok((piece_values, piece_tables)) => {
+                                        info_string(&format!("loaded piece values and tables from {}", value));
+                                        (*engine).piece_values = piece_values;
+                                        (*engine).piece_tables = piece_tables;
+                                    }
+                                    Err(error) => info_string(&format!("could not load weights {}: {}, using the built-in tables", value, error)),
+                                },
+                                Err(error) => info_string(&format!("could not read {}: {}, using the built-in evaluation", value, error)),
+                            }
+                        }
+
+                        "UseNNUE" => {
+                            (*engine).use_nnue = value == "true";
+                            if (*engine).use_nnue && (*engine).network.is_none() {
+                                info_string("no network loaded, set EvalFile first. using the classical evaluation");
+                            }
+                        }
+
+                        "DrawScore" => {
+                            (*engine).draw_value = value.parse::<i32>().unwrap_or_default().clamp(-MAX_DRAW_SCORE, MAX_DRAW_SCORE);
+                        }
+
+                        "FutilityDepth" => {
+                            (*engine).futility_depth = value.parse::<u8>().unwrap_or(DEFAULT_FUTILITY_DEPTH).min(MAX_FUTILITY_DEPTH);
+                        }
+
+                        "FutilityMinPhase" => {
+                            (*engine).futility_min_phase = value.parse::<i32>().unwrap_or(DEFAULT_FUTILITY_MIN_PHASE).clamp(0, MAX_PHASE);
+                        }
+
+                        "FutilityMargin" => {
+                            (*engine).futility_margin = value.parse::<i32>().unwrap_or(DEFAULT_FUTILITY_MARGIN).clamp(0, MAX_FUTILITY_MARGIN);
+                        }
+
+                        "CheckExtension" => {
+                            (*engine).check_extension = value == "true";
+                        }
+
+                        "CaptureExtension" => {
+                            match value.as_str() {
+                                "all" => (*engine).capture_extension = CaptureExtension::All,
+                                "recaptures" => (*engine).capture_extension = CaptureExtension::Recaptures,
+                                "none" => (*engine).capture_extension = CaptureExtension::Off,
+                                _ => info_string(&format!("Unknown command: {}\n Try `setoption name CaptureExtension value all`, `recaptures` or `none`", text.trim())),
+                            }
+                        }
+
+                        "ContemptDynamic" => {
+                            (*engine).contempt_dynamic = value == "true";
+                        }
+
+                        "StableDraw" => {
+                            (*engine).stable_draw = value == "true";
+                        }
+
+                        "UCI_AnalyseMode" => {
+                            (*engine).analyse_mode = value == "true";
+                        }
+
+                        "UCI_ShowWDL" => {
+                            (*engine).show_wdl = value == "true";
+                        }
+
+                        "Opening Variety" => {
+                            (*engine).opening_variety = value.parse::<i32>().unwrap_or_default().clamp(0, OPENING_VARIETY_MAX);
+                        }
+
+                        // harnesses set options of other engines too, so these are ignored rather than errors
+                        _ => info_string(&format!("Ignoring unknown option {}, `uci` lists the supported ones", name))
+                    }
+                }
+
+                _ => info_string(&format!("Unknown command: {}\n Maybe try setoption `name`?", text.trim()))
+            }
+        }
+
+
+        "dumphash" => {
+            let path = lvec[1..].join(" ");
+            match (*engine).dump_hash(&path) {
+                Ok(()) => info_string(&format!("Hash table written to {}", path)),
+                Err(error) => info_string(&format!("Could not write hash table to {}: {}", path, error)),
+            }
+        }
+
+        "loadhash" => {
+            let path = lvec[1..].join(" ");
+            match (*engine).load_hash(&path) {
+                Ok(()) => info_string(&format!("Hash table loaded from {} ({} entries)", path, (*engine).entries_filled())),
+                Err(error) => info_string(&format!("Could not load hash table from {}: {}", path, error)),
+            }
+        }
+
+        "tt" => {
+            info_string(&format!("hash {} MB buckets {} filled {} hashfull {}",
+                (*engine).hash_table_size_mb,
+                (*engine).transposition_table.slots.len() / BUCKET_SLOTS,
+                (*engine).entries_filled(),
+                (*engine).hashfull()));
+        }
+
+        "d" => {
+            (*engine).board.pretty_print()
+        }
+        "why" => {
+            info_string(&explain_last_search(engine));
+        }
+        "options" => {
+            for (name, value) in option_values(engine) {
+                info_string(&format!("option name {} value {}", name, value));
+            }
+        }
+        "validate" => {
+            let moves = lvec.get(1).and_then(|word| word.trim().parse().ok()).unwrap_or(DEFAULT_VALIDATE_MOVES);
+            match validate(engine, moves) {
+                Ok(checked) => info_string(&format!("validate ok, {} moves checked", checked)),
+                Err(mismatch) => info_string(&format!("validate mismatch {}", mismatch)),
+            }
+        }
+        // `tune <file>`: prints the piece values one tuning pass over the file suggests, the engine keeps its own
+        "tune" => {
+            let path = lvec[1..].join(" ");
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(error) => {
+                    info_string(&format!("Could not read {}: {}", path, error));
+                    return;
+                }
+            };
+            let (positions, skipped) = load_tuning_positions(&text);
+            if positions.is_empty() {
+                info_string(&format!("No positions with a result in {}\n Try lines like `<fen> 1-0` or `<fen> [0.5]`", path));
+                return;
+            }
+
+            // the network doesn't use the piece values
+            let (piece_values, use_nnue) = ((*engine).piece_values, (*engine).use_nnue);
+            (*engine).use_nnue = false;
+            let (start, end) = tune_piece_values(engine, &positions);
+            let tuned = (*engine).piece_values;
+            (*engine).piece_values = piece_values;
+            (*engine).use_nnue = use_nnue;
+
+            info_string(&format!("tune {} positions ({} skipped) error {:.6} -> {:.6}", positions.len(), skipped, start, end));
+            for (piece_index, name) in ["PawnValue", "KnightValue", "BishopValue", "RookValue", "QueenValue"].iter().enumerate() {
+                let piece_index = piece_index + 1;
+                if tuned[piece_index] != piece_values[piece_index] {
+                    info_string(&format!("setoption name {} value {} (was {})", name, tuned[piece_index], piece_values[piece_index]));
+                }
+            }
+        }
+        // `testsuite <file> <ms per position>`: searches every EPD position for the given time and checks
+        // the move against its `bm` and `am`, the position set before is kept
+        "testsuite" => {
+            let move_time = match lvec.last().and_then(|word| word.trim().parse::<u32>().ok()) {
+                Some(move_time) if lvec.len() > 2 && move_time > 0 => move_time,
+                _ => {
+                    info_string(&format!("Unknown command: {}\n Try `testsuite wac.epd 1000`", text.trim()));
+                    return;
+                }
+            };
+            let path = lvec[1..lvec.len() - 1].join(" ");
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(error) => {
+                    info_string(&format!("Could not read {}: {}", path, error));
+                    return;
+                }
+            };
+            let (positions, skipped) = load_test_positions(&text);
+            if positions.is_empty() {
+                info_string(&format!("No positions with a bm or am in {}\n Try lines like `<fen> bm Qg6; id \"WAC.001\";`", path));
+                return;
+            }
+
+            let board = (*engine).board.parallel_clone();
+            let mut solved = 0;
+            for position in positions.iter() {
+                (*engine).board = position.board.shallow_clone();
+                (*engine).movetime = move_time;
+                (*engine).target_time = move_time;
+                (*engine).search_stopped.store(false, Ordering::Relaxed);
+                (*engine).quiet = true;
+                let best_move = search(engine);
+                (*engine).quiet = false;
+
+                let passed = (position.best_moves.is_empty() || position.best_moves.contains(&best_move))
+                    && !position.avoid_moves.contains(&best_move);
+                if passed {
+                    solved += 1;
+                }
+                let expected = [("bm", &position.best_moves), ("am", &position.avoid_moves)].iter()
+                    .filter(|(_, moves)| !moves.is_empty())
+                    .map(|(operation, moves)| format!("{} {}", operation, moves.iter().map(|mv| mv.to_string()).collect::<Vec<String>>().join(" ")))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                info_string(&format!("testsuite {} {} move {} {} score cp {}", position.id, { if passed { "pass" } else { "fail" } }, best_move, expected, (*engine).pv_score));
+            }
+            (*engine).board = board;
+
+            info_string(&format!("testsuite solved {} of {} ({} skipped)", solved, positions.len(), skipped));
+        }
+        // `window <depth> <alpha> <beta>`, scores in cp for the side to move
+        "window" => {
+            let numbers: Vec<i32> = lvec[1..].iter().filter_map(|word| word.trim().parse().ok()).collect();
+            match numbers[..] {
+                [depth, alpha, beta] if depth > 0 && alpha < beta => {
+                    let (best_move, score, bound) = (*engine).search_window(depth.min(MAX_DEPTH_LIMIT as i32) as u8, alpha, beta);
+                    let bound = match bound {
+                        BOUND_LOWER => "lowerbound",
+                        BOUND_UPPER => "upperbound",
+                        _ => "exact",
+                    };
+                    // failing low leaves no move better than the others
+                    let best_move = { if best_move.is_null() { "none".to_string() } else { best_move.to_string() } };
+                    info_string(&format!("window depth {} score cp {} {} move {} nodes {}", depth, score, bound, best_move, (*engine).total_nodes()));
+                }
+                _ => info_string(&format!("Unknown command: {}\n Try `window 6 199 200`", text.trim())),
+            }
+        }
+        "make" => {
+            match lvec.get(1) {
+                Some(word) => apply_uci_moves(&mut (*engine).board, &[word]),
+                None => info_string(&format!("Unknown command: {}\n Try `make e2e4`", text.trim())),
+            }
+        }
+        "undo" => {
+            if (*engine).board.last_move().is_some() {
+                (*engine).board.undo_move();
+            } else {
+                info_string("No move to undo");
+            }
+        }
+        "uci" => {
+            println!("id name TissousleBot");
+            println!("id author Tissousle");
+            println!("");
+            println!("option name Hash type spin default 16 min {} max {}", MIN_HASH_MB, MAX_HASH_MB);
+            println!("option name HashAgeLimit type spin default {} min 1 max {}", DEFAULT_HASH_AGE_LIMIT, GENERATION_MASK);
+            println!("option name EvalCache type spin default {} min 0 max {}", DEFAULT_EVAL_CACHE_MB, MAX_EVAL_CACHE_MB);
+            println!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS);
+            println!("option name Thread Mode type combo default lazysmp var lazysmp var rootsplit");
+            println!("option name Ponder type check default false");
+            println!("option name Move Overhead type spin default {} min 0 max {}", DEFAULT_MOVE_OVERHEAD, MAX_MOVE_OVERHEAD);
+            println!("option name NodesTime type spin default 0 min 0 max {}", MAX_NODES_TIME);
+            println!("option name MultiPV type spin default 1 min 1 max {}", MAX_MULTIPV);
+            println!("option name MaxDepth type spin default {} min 1 max {}", DEFAULT_MAX_DEPTH, MAX_DEPTH_LIMIT);
+            // UCI has no fractional spin, so these two are strings holding a number
+            println!("option name LMRBase type string default {}", LMR_BASE);
+            println!("option name LMRDivisor type string default {}", LMR_DIVISOR);
+            println!("option name FutilityDepth type spin default {} min 0 max {}", DEFAULT_FUTILITY_DEPTH, MAX_FUTILITY_DEPTH);
+            println!("option name FutilityMargin type spin default {} min 0 max {}", DEFAULT_FUTILITY_MARGIN, MAX_FUTILITY_MARGIN);
+            println!("option name FutilityMinPhase type spin default {} min 0 max {}", DEFAULT_FUTILITY_MIN_PHASE, MAX_PHASE);
+            println!("option name CheckExtension type check default false");
+            println!("option name CaptureExtension type combo default none var all var recaptures var none");
+            println!("option name AspirationWindow type spin default {} min 0 max {}", DEFAULT_ASPIRATION_WINDOW, MAX_ASPIRATION_WINDOW);
+            println!("option name Skill Level type spin default {} min 0 max {}", MAX_SKILL_LEVEL, MAX_SKILL_LEVEL);
+            println!("option name PawnValue type spin default {} min 0 max 1000", DEFAULT_PIECE_VALUES[1]);
+            println!("option name KnightValue type spin default {} min 0 max 2000", DEFAULT_PIECE_VALUES[2]);
+            println!("option name BishopValue type spin default {} min 0 max 2000", DEFAULT_PIECE_VALUES[3]);
+            println!("option name RookValue type spin default {} min 0 max 3000", DEFAULT_PIECE_VALUES[4]);
+            println!("option name QueenValue type spin default {} min 0 max 5000", DEFAULT_PIECE_VALUES[5]);
+            println!("option name DrawOffset type spin default 0 min -1000 max 1000");
+            println!("option name ContemptDynamic type check default false");
+            println!("option name StableDraw type check default false");
+            println!("option name DrawScore type spin default 0 min -{} max {}", MAX_DRAW_SCORE, MAX_DRAW_SCORE);
+            println!("option name Opening Variety type spin default 0 min 0 max {}", OPENING_VARIETY_MAX);
+            println!("option name UCI_AnalyseMode type check default false");
+            println!("option name UCI_ShowWDL type check default false");
+            println!("option name Evaluation type combo default full var classic var full");
+            println!("option name EvalFile type string default <empty>");
+            println!("option name SearchLog type string default <empty>");
+            println!("option name UseNNUE type check default false");
+            println!("option name UCI_Opponent type string default <empty>");
+            println!("uciok");
+        },
+        "isready" =>
+            println!("readyok"),
+        "register" => {
+            // no registration is required, so `register later` needs no reply and
+            // any name/code pair is simply accepted
+            if lvec.len() > 1 && lvec[1] != "later" {
+                println!("registration checking");
+                println!("registration ok");
+            }
+        }
+        "debug" => {
+            match lvec.get(1) {
+                Some(&"on") => (*engine).debug = true,
+                Some(&"off") => (*engine).debug = false,
+                _ => info_string(&format!("Unknown command: {}\n Try `debug on` or `debug off`", text.trim())),
+            }
+        }
+        "ucinewgame" => 
+            (*engine).clear_history(),
+        // both already took effect in the input thread
+        "stop" | "ponderhit" => (),
+        "quit" =>
+            (*engine).active = false,
+        _ => 
+            info_string(&format!("Unknown command: {}", text.trim())),
+    }
+}
+
+// Reads stdin on its own thread so `stop` and `ponderhit` reach a running search, every line
+// is also passed on to the main loop
+// `input` and `output` are stdin and stdout outside of tests
+fn spawn_input_thread<R: BufRead + Send + 'static, W: Write + Send + 'static>(engine:&Engine, mut input:R, mut output:W) -> mpsc::Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    let search_stopped = Arc::clone(&engine.search_stopped);
+    let pondering = Arc::clone(&engine.pondering);
+    let searching = Arc::clone(&engine.searching);
+
+    thread::spawn(move || {
+        loop {
+            let mut text = String::new();
+
+            match input.read_line(&mut text) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => (),
+            }
+
+            let mut words = text.split_whitespace();
+            match words.next() {
+                Some("go") => {
+                    let words: Vec<&str> = words.collect();
+                    searching.store(true, Ordering::Relaxed);
+                    search_stopped.store(false, Ordering::Relaxed);
+                    pondering.store(words.contains(&"ponder"), Ordering::Relaxed);
+                }
+                // a search that hasn't started yet still answers, from its first iteration at least
+                Some("stop") => search_stopped.store(true, Ordering::Relaxed),
+                // the main loop only reads `quit` once `go` returns, so any running search is stopped
+                // whatever its limits, it answers with the best move so far and the loop quits after
+                Some("quit") if searching.load(Ordering::Relaxed) => search_stopped.store(true, Ordering::Relaxed),
+                Some("ponderhit") => pondering.store(false, Ordering::Relaxed),
+                // a search is no reason not to be ready, it goes on. otherwise the main loop answers
+                // once the commands before this one are done
+                Some("isready") if searching.load(Ordering::Relaxed) => {
+                    if writeln!(output, "readyok").and_then(|_| output.flush()).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                _ => (),
+            }
+
+            if sender.send(text).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+fn main() {
+    
+    
+
+    // the search never recurses deeper than MAX_SEARCH_PLY, the default stack is enough
+    let mut engine = Engine::new(16);
+
+    
+
+    let input = spawn_input_thread(&engine, BufReader::new(io::stdin()), io::stdout());
+
+    while engine.active {
+
+        // the input thread hangs up at the end of stdin, which counts as `quit`
+        let text = match input.recv() {
+            Ok(text) => text,
+            Err(_) => {
+                engine.active = false;
+                break;
+            }
+        };
+
+        com(&text, &mut engine);
+
+        
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a small network with weights that differ per feature, so any misplaced piece shows
+    fn test_network() -> Network {
+        let hidden = 8;
+        Network {
+            hidden,
+            input_weights: (0..NNUE_INPUTS * hidden).map(|index| (index * 37 % 101) as i16 - 50).collect(),
+            hidden_biases: (0..hidden).map(|index| index as i16).collect(),
+            output_weights: (0..hidden).map(|index| index as i16 - 4).collect(),
+            output_bias: 3,
+        }
+    }
+
+    fn engine_at(fen:&str) -> Engine {
+        let mut engine = Engine::new(MIN_HASH_MB);
+        engine.board = Board::from_fen(fen).unwrap();
+        engine.start_line();
+        engine
+    }
+
+    fn evaluate_root(engine:&mut Engine) -> i32 {
+        let board = engine.board.shallow_clone();
+        evaluate(engine, &board)
+    }
+
+    // a silent search of `engine.board` limited by depth alone
+    fn search_to_depth(engine:&mut Engine, depth:u8) -> BitMove {
+        engine.quiet = true;
+        engine.depth = depth;
+        engine.movetime = u32::MAX;
+        engine.target_time = u32::MAX;
+        // the input thread clears it when it reads `go`
+        engine.search_stopped.store(false, Ordering::Relaxed);
+        search(engine)
+    }
+
+    #[test]
+    fn null_move_and_undo_restore_board_key_and_accumulator() {
+        let mut engine = engine_at("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4");
+        engine.network = Some(Arc::new(test_network()));
+        engine.use_nnue = true;
+        let mut board = engine.board.shallow_clone();
+        let mv = board.generate_moves()[0];
+        engine.make_move(&mut board, mv);
+
+        let fen = board.fen();
+        let key = position_key(&board);
+        let ply = engine.ply(&board);
+        let accumulators = engine.accumulators.clone();
+        let position_keys = engine.position_keys.clone();
+
+        engine.make_null_move(&mut board);
+        assert_ne!(position_key(&board), key);
+        assert_eq!(engine.ply(&board), ply + 1);
+        assert_eq!(engine.accumulators.last().unwrap(), &(position_key(&board), test_network().refresh(&board)));
+
+        engine.unmake_null_move(&mut board);
+        assert_eq!(board.fen(), fen);
+        assert_eq!(position_key(&board), key);
+        assert_eq!(engine.ply(&board), ply);
+        assert_eq!(engine.accumulators, accumulators);
+        assert_eq!(engine.position_keys, position_keys);
+    }
+
+    // a few middlegame and endgame positions for tests that walk through games from them
+    const TEST_FENS: [&str; 4] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    ];
+
+    // every position of a fixed, pseudo random walk of `plies` moves from each of TEST_FENS
+    fn test_positions(plies:usize) -> Vec<Board> {
+        let mut positions = Vec::new();
+        for fen in TEST_FENS {
+            let mut board = Board::from_fen(fen).unwrap();
+            for ply in 0..plies {
+                positions.push(board.shallow_clone());
+                let moves = board.generate_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                board.apply_move(moves[(ply * 7 + 3) % moves.len()]);
+            }
+        }
+        positions
+    }
+
+    // EvalInfo the slow way, square by square
+    fn naive_eval_info(board:&Board) -> EvalInfo {
+        let mut info = EvalInfo { pawn_attacks: [BitBoard(0); 2], pawn_attack_spans: [BitBoard(0); 2], king_zones: [BitBoard(0); 2] };
+        for player in [Player::White, Player::Black] {
+            let side = player as usize;
+            for square in board.piece_bb(player, PieceType::P) {
+                let (file, rank) = ((square.0 % 8) as i32, (square.0 / 8) as i32);
+                let forward = { if player == Player::White { 1 } else { -1 } };
+                for attacked_file in [file - 1, file + 1].into_iter().filter(|file| (0..8).contains(file)) {
+                    info.pawn_attacks[side] |= SQ((rank + forward) as u8 * 8 + attacked_file as u8).to_bb();
+                    let mut attacked_rank = rank + forward;
+                    while (0..8).contains(&attacked_rank) {
+                        info.pawn_attack_spans[side] |= SQ(attacked_rank as u8 * 8 + attacked_file as u8).to_bb();
+                        attacked_rank += forward;
+                    }
+                }
+            }
+            let king = board.king_sq(player);
+            let (file, rank) = ((king.0 % 8) as i32, (king.0 / 8) as i32);
+            for zone_file in (file - 1)..=(file + 1) {
+                for zone_rank in (rank - 1)..=(rank + 1) {
+                    if (0..8).contains(&zone_file) && (0..8).contains(&zone_rank) {
+                        info.king_zones[side] |= SQ(zone_rank as u8 * 8 + zone_file as u8).to_bb();
+                    }
+                }
+            }
+        }
+        info
+    }
+
+    #[test]
+    fn eval_info_from_the_pawn_hash_matches_a_naive_recompute() {
+        let mut engine = Engine::new(MIN_HASH_MB);
+        for board in test_positions(60) {
+            let naive = naive_eval_info(&board);
+            assert_eq!(engine.eval_info(&board), naive, "{}", board.fen());
+            assert_eq!(EvalInfo::new(&board), naive, "{}", board.fen());
+
+            // nothing cached, every term builds its own bitboards
+            let mut fresh = Engine::new(MIN_HASH_MB);
+            fresh.clear_eval_cache();
+            let mut naive_terms = vec![
+                ("mobility", mobility(&board, &naive_eval_info(&board))),
+                ("outposts", outposts(&board, &naive_eval_info(&board))),
+            ];
+            if game_phase(&board) > ENDGAME_PHASE {
+                naive_terms.push(("king safety", king_safety(&board, &naive_eval_info(&board))));
+            }
+            let terms = explain_evaluation(&mut engine, &board);
+            for (name, score) in naive_terms {
+                assert!(terms.contains(&(name, score)), "{} {} {:?}", board.fen(), name, terms);
+            }
+            assert_eq!(evaluate(&mut engine, &board), evaluate(&mut fresh, &board), "{}", board.fen());
+        }
+    }
+
+    #[test]
+    fn two_threads_report_the_sum_of_their_node_counts() {
+        let mut engine = engine_at(TEST_FENS[2]);
+        engine.change_threads(2);
+        search_to_depth(&mut engine, 6);
+
+        let per_thread: Vec<u64> = engine.nodes.iter().map(|count| count.load(Ordering::Relaxed)).collect();
+        assert_eq!(per_thread.len(), 2);
+        assert!(per_thread.iter().all(|&nodes| nodes > 0), "{:?}", per_thread);
+        assert_eq!(engine.total_nodes(), per_thread.iter().sum::<u64>());
+    }
+
+    #[test]
+    fn quiet_move_with_bad_history_is_reduced_more() {
+        let mut engine = engine_at(TEST_FENS[2]);
+        let board = engine.board.shallow_clone();
+        let quiets: Vec<BitMove> = board.generate_moves().iter().copied()
+            .filter(|&mv| is_quiet(mv) && !board.gives_check(mv))
+            .collect();
+        let (bad, neutral) = (quiets[0], quiets[1]);
+
+        let side = board.turn() as usize;
+        engine.history[side][bad.get_src_u8() as usize][bad.get_dest_u8() as usize] = -HISTORY_MAX;
+
+        let (depth, move_index) = (8, 10);
+        let bad_reduction = late_move_reduction(&engine, &board, bad, depth, move_index, false);
+        let neutral_reduction = late_move_reduction(&engine, &board, neutral, depth, move_index, false);
+        assert!(bad_reduction > neutral_reduction, "{} vs {}", bad_reduction, neutral_reduction);
+    }
+
+    #[test]
+    fn countermove_is_ordered_ahead_of_other_quiet_moves() {
+        let mut engine = engine_at(TEST_FENS[0]);
+        let mut board = engine.board.shallow_clone();
+        let opponent_move = find_move(&board.generate_moves(), "e2e4").unwrap();
+        board.apply_move(opponent_move);
+        let refutation = find_move(&board.generate_moves(), "a7a6").unwrap();
+
+        let before = gen_and_order_moves(&engine, &mut board, opponent_move);
+        engine.store_countermove(&board, opponent_move, refutation);
+        let after = gen_and_order_moves(&engine, &mut board, opponent_move);
+
+        let position = |moves:&MoveList, mv:BitMove| moves.iter().position(|&other| other == mv).unwrap();
+        assert!(position(&before, refutation) > 0);
+        assert_eq!(position(&after, refutation), 0);
+        // only after the move it refutes
+        let other_move = find_move(&engine.board.generate_moves(), "d2d4").unwrap();
+        let mut other_board = engine.board.shallow_clone();
+        other_board.apply_move(other_move);
+        assert_ne!(gen_and_order_moves(&engine, &mut other_board, other_move)[0], refutation);
+    }
+
+    #[test]
+    fn stalemate_and_repetition_return_the_draw_score() {
+        // white to move has no legal move and isn't in check
+        let mut engine = engine_at("8/8/8/8/8/6k1/5q2/7K w - - 0 1");
+        assert_eq!(evaluate_root(&mut engine), 0);
+        engine.draw_value = 25;
+        engine.clear_eval_cache();
+        assert_eq!(evaluate_root(&mut engine), 25);
+
+        // the same from black's side, the score stays white's point of view
+        let mut engine = engine_at("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        engine.draw_value = 25;
+        assert_eq!(evaluate_root(&mut engine), -25);
+
+        // a position the line already went through scores the same inside the search
+        let mut engine = engine_at("6k1/6p1/6Q1/8/8/8/rr6/6K1 w - - 0 1");
+        engine.draw_value = 25;
+        engine.start_line();
+        let mut board = engine.board.shallow_clone();
+        for text in ["g6e8", "g8h7", "e8h5", "h7g8", "h5e8"] {
+            let mv = find_move(&board.generate_moves(), text).unwrap();
+            engine.make_move(&mut board, mv);
+        }
+        assert!(engine.repeats(&board));
+        assert_eq!(minimax(&mut engine, &mut board, 3, MINIMUM_EVAL, MAXIMUM_EVAL, 0, BitMove::null(), false).1, -25);
+    }
+
+    #[test]
+    fn opening_variety_keeps_a_clearly_best_move() {
+        // Nxd4 wins the queen
+        let fen = "rnb1kbnr/pppp1ppp/8/4p3/3qP3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3";
+        for _ in 0..10 {
+            let mut engine = engine_at(fen);
+            engine.opening_variety = OPENING_VARIETY_MAX;
+            assert_eq!(search_to_depth(&mut engine, 3).to_string(), "f3d4");
+        }
+
+        // while equal moves do get shuffled at the root
+        let mut engine = engine_at(TEST_FENS[0]);
+        let board = engine.board.shallow_clone();
+        assert!(!engine.opening_variety_active(&board));
+        engine.opening_variety = OPENING_VARIETY_MAX;
+        assert!(engine.opening_variety_active(&board));
+        let moves = board.generate_moves();
+        let lines = [(moves[0], 20), (moves[1], 15), (moves[2], -5)];
+        assert!((0..50).any(|_| pick_variety_move(&engine, &lines, 1) == moves[1]));
+        assert!((0..50).all(|_| pick_variety_move(&engine, &lines, 1) != moves[2]));
+    }
+
+    #[test]
+    fn opening_variety_never_plays_a_hanging_piece() {
+        // after 1.e4 e5 2.Nf3 Nc6 the best moves score well inside the decisive limit, Na3 hangs the knight
+        let mut engine = engine_at(TEST_FENS[0]);
+        com(&"position startpos moves e2e4 e7e5 g1f3 b8c6".to_string(), &mut engine);
+        com(&"setoption name Opening Variety value 20".to_string(), &mut engine);
+        let mut played = Vec::new();
+        for _ in 0..8 {
+            let best_move = search_to_depth(&mut engine, 5);
+            let best = engine.pv_lines.iter().map(|line| line.1).max().unwrap();
+            assert!(best.abs() <= OPENING_VARIETY_SCORE_LIMIT);
+            let line = engine.pv_lines.iter().find(|line| line.0 == best_move).unwrap();
+            assert!(line.1 >= best - 20, "{} at {} with the best at {}", best_move, line.1, best);
+            assert!(best_move.to_string() != "b1a3");
+            played.push(best_move);
+        }
+
+        // and as a line of its own, scored exactly, it is never close enough to be picked
+        let board = engine.board.shallow_clone();
+        let hanging = board.generate_moves().iter().copied().find(|mv| mv.to_string() == "b1a3").unwrap();
+        let lines = [(played[0], 45), (hanging, -120)];
+        assert!((0..50).all(|_| pick_variety_move(&engine, &lines, 1) == played[0]));
+    }
+
+    #[test]
+    fn hash_dump_round_trips_and_rejects_a_different_version() {
+        let path = std::env::temp_dir().join(format!("hash-dump-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut engine = engine_at(TEST_FENS[1]);
+        search_to_depth(&mut engine, 4);
+        assert!(engine.entries_filled() > 0);
+        engine.dump_hash(path).unwrap();
+
+        let mut loaded = Engine::new(MIN_HASH_MB);
+        loaded.load_hash(path).unwrap();
+        assert_eq!(loaded.entries_filled(), engine.entries_filled());
+        for index in 0..engine.transposition_table.slots.len() {
+            let (saved, reloaded) = (engine.transposition_table.load(index), loaded.transposition_table.load(index));
+            assert_eq!(reloaded.hash, saved.hash);
+            if saved.hash != 0 {
+                assert_eq!((reloaded.score, reloaded.depth, reloaded.bound, reloaded.best_move), (saved.score, saved.depth, saved.bound, saved.best_move));
+            }
+        }
+
+        // the version follows the 8 byte magic
+        let mut bytes = std::fs::read(path).unwrap();
+        bytes[8..12].copy_from_slice(&(HASH_DUMP_VERSION + 1).to_le_bytes());
+        std::fs::write(path, &bytes).unwrap();
+        let error = Engine::new(MIN_HASH_MB).load_hash(path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn allocate_time_splits_standard_time_controls() {
+        let overhead = DEFAULT_MOVE_OVERHEAD;
+        // 5+0 blitz, 30 moves assumed left
+        assert_eq!(allocate_time(Player::White, 300_000, 300_000, 0, 0, 0, 0, overhead), (9_998, 29_994));
+        // 3+2, three quarters of the increment on top
+        assert_eq!(allocate_time(Player::White, 180_000, 180_000, 2_000, 2_000, 0, 0, overhead), (7_498, 22_494));
+        // 40 moves in 2 hours
+        assert_eq!(allocate_time(Player::White, 7_200_000, 7_200_000, 0, 0, 40, 0, overhead), (179_998, 539_994));
+        // black reads its own clock and increment
+        assert_eq!(allocate_time(Player::Black, 1_000, 60_000, 5_000, 1_000, 0, 0, overhead), (2_748, 8_244));
+    }
+
+    #[test]
+    fn a_root_score_drop_extends_the_target_up_to_the_maximum() {
+        let mut engine = Engine::new(MIN_HASH_MB);
+        engine.target_time = 1_000;
+        engine.movetime = 3_000;
+        engine.extend_on_score_drop(20, 20 - PANIC_SCORE_DROP + 1);
+        assert_eq!(engine.target_time, 1_000);
+        engine.extend_on_score_drop(20, 20 - PANIC_SCORE_DROP);
+        assert_eq!(engine.target_time, 1_000 * PANIC_TIME_FACTOR);
+        engine.extend_on_score_drop(20, -200);
+        assert_eq!(engine.target_time, 3_000);
+    }
+
+    #[test]
+    fn quiescence_in_check_tries_a_quiet_king_evasion() {
+        // Kh2 is the only legal move and it captures nothing
+        let mut engine = engine_at("k7/8/8/8/8/7P/6P1/r6K w - - 0 1");
+        let mut board = engine.board.shallow_clone();
+        let moves = board.generate_moves();
+        assert_eq!(moves.len(), 1);
+        assert!(!moves[0].is_capture());
+
+        let score = quiescence(&mut engine, &mut board, MINIMUM_EVAL, MAXIMUM_EVAL);
+        engine.make_move(&mut board, moves[0]);
+        let after_evasion = quiescence(&mut engine, &mut board, MINIMUM_EVAL, MAXIMUM_EVAL);
+        assert_eq!(score, after_evasion);
+        assert!(score > -9_000_000);
+    }
+
+    #[test]
+    fn analyse_mode_keeps_searching_where_play_stops_early() {
+        // a single legal move is answered after one ply in play
+        let forced = "k7/8/8/8/8/7P/6P1/r6K w - - 0 1";
+        let mut playing = engine_at(forced);
+        search_to_depth(&mut playing, 6);
+        let mut analysing = engine_at(forced);
+        analysing.analyse_mode = true;
+        search_to_depth(&mut analysing, 6);
+        assert!(analysing.total_nodes() > playing.total_nodes());
+
+        // and past the target time with a stable best move
+        let searched_nodes = |analyse_mode:bool| {
+            let mut engine = engine_at(TEST_FENS[2]);
+            engine.analyse_mode = analyse_mode;
+            engine.quiet = true;
+            engine.depth = 5;
+            engine.movetime = u32::MAX;
+            engine.target_time = 0;
+            engine.search_stopped.store(false, Ordering::Relaxed);
+            search(&mut engine);
+            engine.total_nodes()
+        };
+        assert!(searched_nodes(true) > searched_nodes(false));
+    }
+
+    #[test]
+    fn checkmated_and_stalemated_roots_have_no_best_move() {
+        let mut mated = engine_at("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert_eq!(game_over(&mated.board), Some(("mate 0", "checkmate")));
+        assert!(search_to_depth(&mut mated, 4).is_null());
+
+        let mut stalemated = engine_at("8/8/8/8/8/6k1/5q2/7K w - - 0 1");
+        assert_eq!(game_over(&stalemated.board), Some(("cp 0", "stalemate")));
+        assert!(search_to_depth(&mut stalemated, 4).is_null());
+
+        assert_eq!(game_over(&Board::start_pos()), None);
+    }
+
+    #[test]
+    fn ponderhit_and_stop_end_a_ponder_search() {
+        let (reader, mut writer) = io::pipe().unwrap();
+        let mut engine = Engine::new(MIN_HASH_MB);
+        let input = spawn_input_thread(&engine, BufReader::new(reader), io::sink());
+        let pondering = Arc::clone(&engine.pondering);
+        let search_stopped = Arc::clone(&engine.search_stopped);
+
+        // the clock is ignored while pondering
+        writeln!(writer, "go ponder wtime 1000 btime 1000").unwrap();
+        let go = input.recv().unwrap();
+        assert!(pondering.load(Ordering::Relaxed));
+        let ponder_search = thread::spawn(move || {
+            com(&go, &mut engine);
+            engine
+        });
+        thread::sleep(Duration::from_millis(300));
+        assert!(!ponder_search.is_finished());
+
+        // on `ponderhit` the time since `go` counts, which is already past the maximum
+        writeln!(writer, "ponderhit").unwrap();
+        assert_eq!(input.recv().unwrap().trim(), "ponderhit");
+        assert!(!pondering.load(Ordering::Relaxed));
+        assert!(!search_stopped.load(Ordering::Relaxed));
+        let mut engine = ponder_search.join().unwrap();
+        assert!(!engine.pv.is_empty());
+        assert!(!engine.searching.load(Ordering::Relaxed));
+
+        // `stop` answers a ponder search that the opponent didn't play into
+        writeln!(writer, "go ponder wtime 1000 btime 1000").unwrap();
+        let go = input.recv().unwrap();
+        let ponder_search = thread::spawn(move || {
+            com(&go, &mut engine);
+            engine
+        });
+        thread::sleep(Duration::from_millis(100));
+        assert!(!ponder_search.is_finished());
+        writeln!(writer, "stop").unwrap();
+        assert_eq!(input.recv().unwrap().trim(), "stop");
+        let engine = ponder_search.join().unwrap();
+        assert!(!engine.pv.is_empty());
+        assert!(pondering.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn an_illegal_move_is_rejected_without_touching_the_board() {
+        let mut engine = Engine::new(MIN_HASH_MB);
+        // the king can't jump two squares forward, the moves before it still apply
+        com(&"position startpos moves e2e4 e7e5 e1e3 g1f3".to_string(), &mut engine);
+        let mut expected = Board::start_pos();
+        apply_uci_moves(&mut expected, &["e2e4", "e7e5"]);
+        assert_eq!(engine.board.fen(), expected.fen());
+
+        // no pawn moves three squares, only the legal move is searched
+        let mut engine = Engine::new(MIN_HASH_MB);
+        engine.search_stopped.store(false, Ordering::Relaxed);
+        com(&"go depth 1 searchmoves e2e5 d2d4".to_string(), &mut engine);
+        assert_eq!(engine.search_moves.iter().map(|mv| mv.to_string()).collect::<Vec<_>>(), ["d2d4"]);
+        assert_eq!(engine.pv[0].to_string(), "d2d4");
+    }
+
+    #[test]
+    fn multipv_lines_come_from_the_searchmoves() {
+        let mut engine = Engine::new(MIN_HASH_MB);
+        engine.multipv = 4;
+        let legal_moves = engine.board.generate_moves();
+        engine.search_moves = ["e2e4", "d2d4", "g1f3"].iter().map(|text| find_move(&legal_moves, text).unwrap()).collect();
+        search_to_depth(&mut engine, 4);
+
+        // fewer lines than asked for, each a different allowed move
+        let mut moves: Vec<String> = engine.pv_lines.iter().map(|(mv, _)| mv.to_string()).collect();
+        moves.sort();
+        assert_eq!(moves, ["d2d4", "e2e4", "g1f3"]);
+        assert_eq!(engine.pv_lines[0].0, engine.pv[0]);
+
+        // without searchmoves every line is shown
+        let mut engine = Engine::new(MIN_HASH_MB);
+        engine.multipv = 4;
+        search_to_depth(&mut engine, 4);
+        assert_eq!(engine.pv_lines.len(), 4);
+    }
+
+    #[test]
+    fn skill_level_zero_sometimes_plays_a_worse_move_and_twenty_never_does() {
+        let mut engine = Engine::new(MIN_HASH_MB);
+        let legal_moves = engine.board.generate_moves();
+        let (best, worse) = (find_move(&legal_moves, "e2e4").unwrap(), find_move(&legal_moves, "a2a3").unwrap());
+        // a line a pawn better, scored for white and then for black to move
+        for (lines, perspective) in [([(best, 100), (worse, 0)], 1), ([(best, -100), (worse, 0)], -1)] {
+            engine.skill_level = MAX_SKILL_LEVEL;
+            assert!((0..200).all(|_| pick_skill_move(&engine, &lines, perspective) == best));
+            engine.skill_level = 0;
+            assert!((0..200).any(|_| pick_skill_move(&engine, &lines, perspective) == worse));
+        }
+    }
+
+    #[test]
+    fn maxmoves_bounds_the_length_of_the_line() {
+        // Rd8+ Rxd8 Rxd8#, the mate is on the third ply
+        let score_with_max_moves = |max_moves:u16| {
+            let mut engine = engine_at("2r3k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1");
+            engine.search_stopped.store(false, Ordering::Relaxed);
+            com(&format!("go depth 6 maxmoves {}", max_moves), &mut engine);
+            assert_eq!(engine.max_plies, max_moves * 2);
+            engine.pv_score
+        };
+        // depth 6 would see it, the cap doesn't let it
+        assert!(score_with_max_moves(1) < 9_000_000);
+        assert!(score_with_max_moves(2) > 9_000_000);
+    }
+
+    #[test]
+    fn each_evaluation_mode_adds_up_its_own_terms() {
+        // a middlegame, none of the endgame terms apply
+        let mut engine = engine_at(TEST_FENS[1]);
+        let board = engine.board.shallow_clone();
+        assert!(game_phase(&board) > ENDGAME_PHASE);
+        let (material, tables) = material_and_tables(&engine, &board, 0);
+        let info = EvalInfo::new(&board);
+        let full_terms = pawn_structure(&board) + mobility(&board, &info) + outposts(&board, &info)
+            + king_tropism(&board, game_phase(&board)) + king_safety(&board, &info);
+        assert_ne!(full_terms, 0);
+
+        com(&"setoption name Evaluation value classic".to_string(), &mut engine);
+        assert_eq!(evaluate_root(&mut engine), material + tables);
+        com(&"setoption name Evaluation value full".to_string(), &mut engine);
+        assert_eq!(evaluate_root(&mut engine), material + tables + full_terms);
+    }
+
+    #[test]
+    fn an_abort_in_the_first_root_move_keeps_the_last_completed_move() {
+        let mut engine = engine_at(TEST_FENS[1]);
+        let depth_one_move = search_to_depth(&mut engine, 1);
+        let depth_one_nodes = engine.total_nodes();
+
+        // the node limit runs out a few nodes into the second iteration
+        let mut engine = engine_at(TEST_FENS[1]);
+        engine.max_nodes = depth_one_nodes + 3;
+        let best_move = search_to_depth(&mut engine, 10);
+        assert!(engine.total_nodes() < depth_one_nodes + 10);
+        assert_eq!(best_move, depth_one_move);
+        assert_eq!(engine.pv[0], depth_one_move);
+    }
+
+    #[test]
+    fn a_low_clock_leaves_the_move_overhead_unspent() {
+        let overhead = DEFAULT_MOVE_OVERHEAD;
+        for (time, increment) in [(200, 0), (200, 1_000), (60, 0), (30, 2_000)] {
+            let (target, maximum) = allocate_time(Player::White, time, 60_000, increment, 0, 0, 0, overhead);
+            // a target of 0 still completes the first iteration
+            assert!(target <= maximum, "{} {}", time, increment);
+            assert!(maximum + overhead <= time.max(overhead + 1), "{} {}", time, increment);
+        }
+        assert_eq!(allocate_time(Player::White, 200, 60_000, 0, 0, 0, 0, overhead), (5, 15));
+    }
+
+    #[test]
+    fn the_first_aspiration_window_has_the_configured_width() {
+        let aspiration = |window:i32, previous_score:i32| {
+            let mut engine = engine_at(TEST_FENS[2]);
+            engine.aspiration_window = window;
+            engine.movetime = u32::MAX;
+            engine.search_stopped.store(false, Ordering::Relaxed);
+            let mut board = engine.board.shallow_clone();
+            let result = aspiration_search(&mut engine, &mut board, 3, previous_score, BitMove::null());
+            (result.1, engine.aspiration_fail_lows + engine.aspiration_fail_highs)
+        };
+        // 0 is a plain full-window search
+        let (score, fails) = aspiration(0, 0);
+        assert_eq!(fails, 0);
+
+        // a guess 45 cp off fails once with a 30 cp window and not at all with a 100 cp one
+        assert_eq!(aspiration(30, score + 45), (score, 1));
+        assert_eq!(aspiration(100, score + 45), (score, 0));
+        assert_eq!(aspiration(30, score - 45), (score, 1));
+        assert_eq!(aspiration(100, score - 45), (score, 0));
+    }
+
+    #[test]
+    fn allocate_time_covers_every_kind_of_go() {
+        let allocate = |wtime, winc, movestogo, movetime| allocate_time(Player::White, wtime, 60_000, winc, 0, movestogo, movetime, DEFAULT_MOVE_OVERHEAD);
+        // sudden death
+        assert_eq!(allocate(60_000, 0, 0, 0), (1_998, 5_994));
+        // increment
+        assert_eq!(allocate(60_000, 1_000, 0, 0), (2_748, 8_244));
+        // moves to go, the last move before the control may use the whole clock
+        assert_eq!(allocate(10_000, 0, 10, 0), (995, 2_985));
+        assert_eq!(allocate(10_000, 0, 1, 0), (9_950, 9_950));
+        // movetime wins over the clock, no clock at all gets the default
+        assert_eq!(allocate(60_000, 1_000, 0, 1_500), (1_500, 1_500));
+        assert_eq!(allocate_time(Player::White, 0, 0, 0, 0, 0, 0, DEFAULT_MOVE_OVERHEAD), (DEFAULT_MOVETIME, DEFAULT_MOVETIME));
+        // less on the clock than the overhead
+        assert_eq!(allocate(40, 0, 0, 0), (0, 0));
+    }
+
+    #[test]
+    fn pv_walk_stops_at_a_cycle_in_the_table() {
+        let engine = Engine::new(MIN_HASH_MB);
+        let line = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        // every position points at the next knight move, the last one back at the start position
+        let mut board = engine.board.shallow_clone();
+        for (index, text) in line.iter().enumerate() {
+            let mv = find_move(&board.generate_moves(), text).unwrap();
+            board.apply_move(mv);
+            let next = line[(index + 1) % line.len()];
+            engine.transposition_store(&board, 0, find_move(&board.generate_moves(), next).unwrap(), 10, BOUND_EXACT);
+        }
+        assert_eq!(position_key(&board), position_key(&engine.board));
+
+        let root_move = find_move(&engine.board.generate_moves(), line[0]).unwrap();
+        let pv = principal_variation(&engine, &engine.board, root_move, u8::MAX);
+        assert_eq!(pv.iter().map(|mv| mv.to_string()).collect::<Vec<_>>(), line);
+    }
+
+    #[test]
+    fn the_pv_reply_is_the_ponder_move_and_go_ponder_plays_into_it() {
+        let mut engine = Engine::new(MIN_HASH_MB);
+        let best_move = search_to_depth(&mut engine, 5);
+        let pv = engine.pv.clone();
+        assert!(pv.len() >= 2);
+        assert_eq!(pv[0], best_move);
+        assert_eq!(bestmove_text(best_move, &pv), format!("bestmove {} ponder {}", pv[0], pv[1]));
+        assert_eq!(bestmove_text(best_move, &pv[..1]), format!("bestmove {}", best_move));
+
+        // the GUI left the board as it was, `go ponder` searches the position after the reply
+        com(&"go ponder depth 1".to_string(), &mut engine);
+        let mut expected = Board::start_pos();
+        expected.apply_move(pv[0]);
+        expected.apply_move(pv[1]);
+        assert_eq!(engine.board.fen(), expected.fen());
+
+        // a ponder search stopped without `ponderhit` leaves the board as the GUI set it
+        com(&"position startpos".to_string(), &mut engine);
+        search_to_depth(&mut engine, 5);
+        engine.pondering.store(true, Ordering::Relaxed);
+        engine.search_stopped.store(true, Ordering::Relaxed);
+        com(&"go ponder depth 1".to_string(), &mut engine);
+        assert_eq!(engine.board.fen(), Board::start_pos().fen());
+        engine.pondering.store(false, Ordering::Relaxed);
+        com(&"go depth 1".to_string(), &mut engine);
+        assert!(Board::start_pos().generate_moves().contains(&engine.pv[0]));
+    }
+
+    #[test]
+    fn the_king_walks_up_to_shepherd_its_passed_pawn() {
+        // the term itself: our king next to the pawn and theirs far, then the other way round
+        let escorted = Board::from_fen("7k/8/8/8/3KP3/8/8/8 w - - 0 1").unwrap();
+        let abandoned = Board::from_fen("8/8/8/3k4/4P3/8/8/K7 w - - 0 1").unwrap();
+        assert!(passed_pawn_kings(&escorted) > 0);
+        assert!(passed_pawn_kings(&abandoned) < 0);
+
+        // pushing now loses the pawn, the king has to come up first
+        let mut engine = engine_at("8/8/4k3/8/4P3/8/8/4K3 w - - 0 1");
+        let pawn = engine.board.piece_bb(Player::White, PieceType::P).to_sq();
+        let king = engine.board.king_sq(Player::White);
+        let best_move = search_to_depth(&mut engine, 8);
+        assert_eq!(best_move.get_src(), king);
+        assert!(best_move.get_dest().distance(pawn) < king.distance(pawn));
+    }
+
+    #[test]
+    fn root_split_on_two_threads_finds_the_single_thread_move() {
+        for fen in ["rnb1kbnr/pppp1ppp/8/4p3/3qP3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3", "2r3k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1", TEST_FENS[3]] {
+            let mut single = engine_at(fen);
+            let single_move = search_to_depth(&mut single, 6);
+
+            let mut split = engine_at(fen);
+            split.thread_mode = ThreadMode::RootSplit;
+            split.change_threads(2);
+            assert_eq!(search_to_depth(&mut split, 6), single_move, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn rule_of_the_square_decides_an_unstoppable_pawn() {
+        let unstoppable = |fen:&str| unstoppable_pawns(&Board::from_fen(fen).unwrap());
+        // Ke6 is one step outside the square of the a5 pawn, so it's the move that decides
+        assert_eq!(unstoppable("8/8/4k3/P7/8/8/8/7K w - - 0 1"), UNSTOPPABLE_PAWN_BONUS);
+        assert_eq!(unstoppable("8/8/4k3/P7/8/8/8/7K b - - 0 1"), 0);
+        // Kd6 is inside it
+        assert_eq!(unstoppable("8/8/3k4/P7/8/8/8/7K w - - 0 1"), 0);
+        // the same for black
+        assert_eq!(unstoppable("7k/8/8/8/p7/4K3/8/8 b - - 0 1"), -UNSTOPPABLE_PAWN_BONUS);
+        assert_eq!(unstoppable("7k/8/8/8/p7/4K3/8/8 w - - 0 1"), 0);
+        // from the starting rank the double step counts
+        assert_eq!(unstoppable("8/8/8/8/6k1/8/P7/7K w - - 0 1"), UNSTOPPABLE_PAWN_BONUS);
+        // any piece left and the square doesn't apply
+        assert_eq!(unstoppable("8/8/4k3/P7/8/8/8/6NK w - - 0 1"), 0);
+    }
+
+    #[test]
+    fn the_table_move_is_searched_first() {
+        let engine = engine_at(TEST_FENS[1]);
+        let mut board = engine.board.shallow_clone();
+        let ordered = gen_and_order_moves(&engine, &mut board, BitMove::null()).vec();
+        // the quiet move ordered last, behind every capture
+        let table_move = *ordered.last().unwrap();
+        assert!(!table_move.is_capture() && ordered[0].is_capture());
+
+        let mut moves = ordered.clone();
+        table_move_first(&mut moves, table_move);
+        assert_eq!(moves[0], table_move);
+        assert_eq!(moves[1..], ordered[..ordered.len() - 1]);
+
+        // a move that isn't legal here changes nothing
+        let mut moves = ordered.clone();
+        table_move_first(&mut moves, BitMove::null());
+        assert_eq!(moves[..], ordered[..]);
+    }
+
+    #[test]
+    fn nodestime_makes_a_timed_search_repeat_its_node_count() {
+        let timed_nodes = || {
+            let mut engine = engine_at(TEST_FENS[1]);
+            engine.search_stopped.store(false, Ordering::Relaxed);
+            com(&"setoption name NodesTime value 10".to_string(), &mut engine);
+            com(&"go movetime 500".to_string(), &mut engine);
+            engine.total_nodes()
+        };
+        let nodes = timed_nodes();
+        // 500 ms at 10 nodes each, the iteration running past it stops a few nodes later
+        assert!((5_000..5_100).contains(&nodes), "{}", nodes);
+        assert_eq!(timed_nodes(), nodes);
+    }
+
+    #[test]
+    fn see_handles_en_passant_and_pinned_attackers() {
+        let engine = Engine::new(MIN_HASH_MB);
+        let value = |piece_type:PieceType| engine.piece_values[piece_type as usize];
+        let see_of = |fen:&str, text:&str| {
+            let board = Board::from_fen(fen).unwrap();
+            see(&engine, &board, find_move(&board.generate_moves(), text).unwrap())
+        };
+
+        // en passant wins the pawn behind d6, unless the king can take back on d6
+        assert_eq!(see_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6"), value(PieceType::P));
+        assert_eq!(see_of("8/4k3/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6"), 0);
+
+        // the knight defends d5, but not while it's pinned to its king
+        assert_eq!(see_of("7k/8/5n2/3p4/8/2B5/8/3RK3 w - - 0 1", "d1d5"), value(PieceType::P));
+        assert_eq!(see_of("6k1/8/5n2/3p4/8/2B5/8/3RK3 w - - 0 1", "d1d5"), value(PieceType::P) - value(PieceType::R));
+    }
+
+    #[test]
+    fn quiescence_in_check_searches_evasions_instead_of_standing_pat() {
+        // Nc2+ forks king and queen, white is a queen for a knight up but loses the queen after any evasion
+        let mut engine = engine_at("7k/7p/8/8/8/8/2n5/Q3K3 w - - 0 1");
+        let mut board = engine.board.shallow_clone();
+        assert!(board.in_check());
+        let stand_pat = evaluate_root(&mut engine);
+        assert!(stand_pat > 300);
+
+        // the stand-pat alone would fail high on this window, the evasions fail low
+        assert!(quiescence(&mut engine, &mut board, stand_pat - 1, stand_pat) < stand_pat);
+        assert!(quiescence(&mut engine, &mut board, MINIMUM_EVAL, MAXIMUM_EVAL) < 0);
+    }
+
+    #[test]
+    fn a_very_old_entry_is_replaced_under_pressure_and_a_current_one_is_not() {
+        let mut engine = Engine::new(MIN_HASH_MB);
+        let board = Board::start_pos();
+        let key = position_key(&board);
+        let bucket = engine.bucket(key);
+        let entry = |hash:u64, generation:u8| TranspositionObject { hash, score: 0, depth: 20, bound: BOUND_EXACT, generation, best_move: BitMove::null() };
+        let table = Arc::clone(&engine.transposition_table);
+        // a full table, so a store has to evict something
+        table.filled.store(table.slots.len(), Ordering::Relaxed);
+
+        // one entry from this search, one from searches long ago, and a new search begins
+        engine.generation = 10;
+        table.store(bucket, entry(key ^ 1, 10 - engine.hash_age_limit));
+        table.store(bucket + 1, entry(key ^ 2, 10));
+        engine.generation = 11;
+        engine.transposition_store(&board, 0, BitMove::null(), 1, BOUND_EXACT);
+        assert_eq!(table.load(bucket).hash, key);
+        assert_eq!(table.load(bucket + 1).hash, key ^ 2);
+
+        // deeper entries of the current search both stay
+        table.store(bucket, entry(key ^ 1, 11));
+        table.store(bucket + 1, entry(key ^ 2, 11));
+        engine.transposition_store(&board, 0, BitMove::null(), 1, BOUND_EXACT);
+        assert_eq!((table.load(bucket).hash, table.load(bucket + 1).hash), (key ^ 1, key ^ 2));
+    }
+
+    #[test]
+    fn eval_weights_written_out_load_back_to_the_same_evaluation() {
+        let mut built_in = Engine::new(MIN_HASH_MB);
+        let mut text = String::from("# piece values\n");
+        text += &built_in.piece_values.map(|value| value.to_string()).join(" ");
+        for (stage, tables) in built_in.piece_tables.iter().enumerate() {
+            for (piece, table) in tables.iter().enumerate() {
+                text += &format!("\n# stage {} piece {}\n{}", stage, piece, table.map(|value| value.to_string()).join(" "));
+            }
+        }
+        assert_eq!(load_eval_weights(&text), Ok((built_in.piece_values, built_in.piece_tables)));
+
+        let path = std::env::temp_dir().join(format!("eval-weights-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, &text).unwrap();
+        let mut loaded = Engine::new(MIN_HASH_MB);
+        loaded.piece_values = [0; 7];
+        loaded.piece_tables = [[[0; 64]; 7]; 2];
+        com(&format!("setoption name EvalFile value {}", path), &mut loaded);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!((loaded.piece_values, loaded.piece_tables), (built_in.piece_values, built_in.piece_tables));
+        for board in test_positions(12) {
+            assert_eq!(evaluate(&mut loaded, &board), evaluate(&mut built_in, &board), "{}", board.fen());
+        }
+
+        // a number short is an error, not a table with a zero in it
+        let short = text.rsplit_once(' ').unwrap().0;
+        assert!(load_eval_weights(short).is_err());
+    }
+
+    #[test]
+    fn position_fen_with_and_without_moves() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let mut engine = Engine::new(MIN_HASH_MB);
+        com(&format!("position fen {}", fen), &mut engine);
+        assert_eq!(engine.board.fen(), fen);
+
+        // extra whitespace between the tokens changes nothing
+        com(&format!("position   fen  {}   moves  f1b5 \t a7a6 ", fen.replace(' ', "  ")), &mut engine);
+        let mut expected = Board::from_fen(fen).unwrap();
+        apply_uci_moves(&mut expected, &["f1b5", "a7a6"]);
+        assert_eq!(engine.board.fen(), expected.fen());
+
+        // `moves` with nothing after it is the position itself
+        com(&format!("position fen {} moves", fen), &mut engine);
+        assert_eq!(engine.board.fen(), fen);
+
+        // a FEN that's cut short leaves the board alone
+        com(&"position fen rnbqkbnr/pppppppp/8/8 w moves e2e4".to_string(), &mut engine);
+        assert_eq!(engine.board.fen(), fen);
+    }
+
+    #[test]
+    fn combined_limits_stop_at_the_tightest() {
+        let go = |command:&str| {
+            let mut engine = engine_at(TEST_FENS[1]);
+            // time counted in nodes, 10 to the millisecond, so every limit is a node count
+            engine.nodes_time = 10;
+            engine.search_stopped.store(false, Ordering::Relaxed);
+            com(&command.to_string(), &mut engine);
+            engine.total_nodes()
+        };
+        let depth_three = go("go depth 3");
+
+        // depth first
+        assert_eq!(go("go depth 3 nodes 100000000 movetime 100000000"), depth_three);
+        // then nodes, movetime 3000 would be 30000 nodes
+        let nodes = go("go depth 30 nodes 20000 movetime 3000");
+        assert!((20_000..20_100).contains(&nodes), "{}", nodes);
+        // then time
+        let nodes = go("go depth 30 nodes 100000000 movetime 2000");
+        assert!((20_000..20_100).contains(&nodes), "{}", nodes);
+        assert!(depth_three < 20_000);
+    }
+
+    #[test]
+    fn error_messages_are_info_string_lines() {
+        // the hint on its own line gets the prefix as well, without the indent
+        let text = format!("Unknown command: {}\n Try `go {} <number>`", "go depth x", "depth");
+        assert_eq!(info_string_lines(&text), ["info string Unknown command: go depth x", "info string Try `go depth <number>`"]);
+        assert!(info_string_lines("").is_empty());
+    }
+
+    #[test]
+    fn why_explains_the_last_search() {
+        let mut engine = engine_at(TEST_FENS[2]);
+        assert!(explain_last_search(&mut engine).contains("run `go` first"));
+
+        let best_move = search_to_depth(&mut engine, 4);
+        let explanation = explain_last_search(&mut engine);
+        let lines: Vec<&str> = explanation.lines().collect();
+        assert!(lines[0].starts_with(&format!("why bestmove {} score cp ", best_move)));
+        assert!(lines.iter().any(|line| line.starts_with("why material ")));
+        assert!(lines.last().unwrap().starts_with("why total "));
+    }
+
+    #[test]
+    fn a_lost_position_takes_the_threefold_repetition() {
+        // two rooks against a queen, the queen's checks are all white has
+        let mut engine = Engine::new(MIN_HASH_MB);
+        com(&"position fen 6k1/6p1/6Q1/8/8/8/rr6/6K1 w - - 0 1 moves g6e8 g8h7 e8h5 h7g8 h5e8 g8h7 e8h5 h7g8".to_string(), &mut engine);
+        let repetition = find_move(&engine.board.generate_moves(), "h5e8").unwrap();
+        assert_eq!(threefold_move(&engine.board, &engine.board.generate_moves().vec()), Some(repetition));
+        assert_eq!(search_to_depth(&mut engine, 5), repetition);
+        assert_eq!(engine.pv_score, 0);
+    }
+
+    #[test]
+    fn position_moves_starts_from_the_start_position() {
+        let mut engine = engine_at(TEST_FENS[1]);
+        com(&"position moves e2e4 e7e5".to_string(), &mut engine);
+        let mut expected = Board::start_pos();
+        apply_uci_moves(&mut expected, &["e2e4", "e7e5"]);
+        assert_eq!(engine.board.fen(), expected.fen());
+
+        com(&"position moves".to_string(), &mut engine);
+        assert_eq!(engine.board.fen(), Board::start_pos().fen());
+    }
+
+    #[test]
+    fn truncated_commands_are_reported_instead_of_panicking() {
+        for command in ["go depth", "go nodes", "go movetime", "go wtime", "go wtime 1000 btime", "go movestogo", "go depth x", "go searchmoves"] {
+            let mut engine = Engine::new(MIN_HASH_MB);
+            // the default 8 seconds in nodes
+            engine.nodes_time = 1;
+            engine.search_stopped.store(false, Ordering::Relaxed);
+            com(&command.to_string(), &mut engine);
+            // the search still answers
+            assert!(!engine.pv.is_empty(), "{}", command);
+        }
+
+        let mut engine = Engine::new(MIN_HASH_MB);
+        for command in ["setoption", "setoption name", "setoption name Hash", "setoption name Hash value", "setoption name Hash value x"] {
+            com(&command.to_string(), &mut engine);
+            assert_eq!(engine.hash_table_size_mb, MIN_HASH_MB, "{}", command);
+        }
+    }
+
+    #[test]
+    fn an_out_of_range_hash_is_clamped_not_allocated() {
+        assert_eq!(clamp_hash_mb(1_000_000_000), MAX_HASH_MB);
+        assert_eq!(clamp_hash_mb(0), MIN_HASH_MB);
+        assert_eq!(clamp_hash_mb(64), 64);
+
+        // what `setoption` allocates, MAX_HASH_MB itself is left out to keep the test small
+        let mut engine = Engine::new(MIN_HASH_MB);
+        com(&"setoption name Hash value 2".to_string(), &mut engine);
+        assert_eq!(engine.transposition_table.slots.len(), 2 * MB_TO_ITEMS);
+        com(&"setoption name Hash value 0".to_string(), &mut engine);
+        assert_eq!(engine.hash_table_size_mb, MIN_HASH_MB);
+        assert_eq!(engine.transposition_table.slots.len(), MIN_HASH_MB * MB_TO_ITEMS);
+    }
+
+    #[test]
+    fn the_stop_flag_ends_a_search_within_a_few_nodes() {
+        let mut engine = engine_at(TEST_FENS[1]);
+        let search_stopped = Arc::clone(&engine.search_stopped);
+        let nodes = Arc::clone(&engine.nodes);
+        let running = thread::spawn(move || {
+            search_to_depth(&mut engine, 60);
+            engine
+        });
+        thread::sleep(Duration::from_millis(200));
+        assert!(!running.is_finished());
+
+        search_stopped.store(true, Ordering::Relaxed);
+        let nodes_at_stop = nodes[0].load(Ordering::Relaxed);
+        let engine = running.join().unwrap();
+        assert!(engine.total_nodes() - nodes_at_stop < 1_000, "{} after the stop", engine.total_nodes() - nodes_at_stop);
+        assert!(!engine.pv.is_empty());
+    }
+
+    #[test]
+    fn the_lmr_divisor_scales_the_reductions() {
+        let mut engine = Engine::new(MIN_HASH_MB);
+        let board = engine.board.shallow_clone();
+        let quiet = find_move(&board.generate_moves(), "a2a3").unwrap();
+        let reduction = |engine:&Engine| late_move_reduction(engine, &board, quiet, 8, 20, false);
+
+        // depth 8, move index 20 and no history, so straight from the formula
+        let expected = |divisor:f32| (LMR_BASE + f32::ln(8.0) * f32::ln(20.0) / divisor) as u8;
+        assert_eq!(reduction(&engine), expected(LMR_DIVISOR));
+        assert_eq!(reduction(&engine), 3);
+        com(&"setoption name LMRDivisor value 4.5".to_string(), &mut engine);
+        assert_eq!(reduction(&engine), expected(4.5));
+        assert_eq!(reduction(&engine), 2);
+        com(&"setoption name LMRDivisor value 1".to_string(), &mut engine);
+        assert_eq!(reduction(&engine), 6);
+        // the divisor has a floor and the reduction leaves at least one ply
+        com(&"setoption name LMRDivisor value 0".to_string(), &mut engine);
+        assert_eq!(engine.lmr_divisor, LMR_MIN_DIVISOR);
+        assert_eq!(reduction(&engine), 7);
+    }
+
+    #[test]
+    fn a_win_near_the_fifty_move_limit_resets_the_clock() {
+        let engine = Engine::new(MIN_HASH_MB);
+        let fresh = Board::from_fen("8/8/8/4k3/8/8/P7/R3K3 w - - 0 120").unwrap();
+        let late = Board::from_fen("8/8/8/4k3/8/8/P7/R3K3 w - - 90 120").unwrap();
+        assert_eq!(fifty_move_scale(&engine, &fresh, 800), 800);
+        assert_eq!(fifty_move_scale(&engine, &late, 800), 200);
+
+        // any rook or king move leaves a few plies before the draw, a pawn move starts the count again
+        let mut engine = engine_at("8/8/8/4k3/8/8/P7/R3K3 w - - 95 120");
+        let best_move = search_to_depth(&mut engine, 6);
+        assert_eq!(best_move.get_src(), SQ::A2, "{}", best_move);
+        assert!(engine.pv_score > 500);
+    }
+
+    #[test]
+    fn equal_multipv_lines_keep_their_places_between_depths() {
+        let (a, b, c) = (BitMove::new(1), BitMove::new(2), BitMove::new(3));
+        // for white the highest score first, ties as ranked before and new lines last
+        let mut lines = [(c, 10), (a, 10), (b, 30)];
+        rank_root_lines(&mut lines, &[a], 1);
+        assert_eq!(lines, [(b, 30), (a, 10), (c, 10)]);
+        // for black the lowest
+        let mut lines = [(c, 10), (a, 10), (b, 30)];
+        rank_root_lines(&mut lines, &[c, a], -1);
+        assert_eq!(lines, [(c, 10), (a, 10), (b, 30)]);
+
+        // a symmetric position with a row of equal moves at every depth
+        let lines_at = |depth:u8| {
+            let mut engine = engine_at("4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1");
+            engine.multipv = 6;
+            search_to_depth(&mut engine, depth);
+            engine.pv_lines
+        };
+        let (third, fourth) = (lines_at(3), lines_at(4));
+        assert!(fourth.iter().all(|&(_, score)| score == fourth[0].1));
+        assert_eq!(fourth.iter().map(|&(mv, _)| mv).collect::<Vec<_>>(), third.iter().map(|&(mv, _)| mv).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn a_null_window_probe_on_a_winning_position() {
+        // a queen up, for white and then for black to move
+        for fen in ["4k3/8/8/8/8/8/8/3QK3 w - - 0 1", "3qk3/8/8/8/8/8/8/4K3 b - - 0 1"] {
+            let mut engine = engine_at(fen);
+            // at least +2?
+            let (best_move, score, bound) = engine.search_window(4, 199, 200);
+            assert_eq!(bound, BOUND_LOWER, "{}", fen);
+            assert!(score >= 200 && !best_move.is_null(), "{}", fen);
+            // but not a mate
+            let (_, score, bound) = engine.search_window(4, 9_000_000, 9_000_001);
+            assert_eq!(bound, BOUND_UPPER, "{}", fen);
+            assert!(score <= 9_000_000, "{}", fen);
+            // and a full window is exact
+            assert_eq!(engine.search_window(4, MINIMUM_EVAL, MAXIMUM_EVAL).2, BOUND_EXACT, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn isready_during_a_search_answers_without_stopping_it() {
+        let (reader, mut writer) = io::pipe().unwrap();
+        let (output_reader, output_writer) = io::pipe().unwrap();
+        let mut output = BufReader::new(output_reader);
+        let mut engine = engine_at(TEST_FENS[1]);
+        let input = spawn_input_thread(&engine, BufReader::new(reader), output_writer);
+        let search_stopped = Arc::clone(&engine.search_stopped);
+
+        writeln!(writer, "go depth 60").unwrap();
+        let go = input.recv().unwrap();
+        let running = thread::spawn(move || {
+            com(&go, &mut engine);
+            engine
+        });
+        thread::sleep(Duration::from_millis(100));
+
+        writeln!(writer, "isready").unwrap();
+        let mut line = String::new();
+        output.read_line(&mut line).unwrap();
+        assert_eq!(line.trim(), "readyok");
+        assert!(!search_stopped.load(Ordering::Relaxed));
+        assert!(!running.is_finished());
+
+        // answered by the input thread, so the next command the main loop sees is the `stop`
+        writeln!(writer, "stop").unwrap();
+        assert_eq!(input.recv().unwrap().trim(), "stop");
+        assert!(!running.join().unwrap().pv.is_empty());
+    }
+
+    #[test]
+    fn every_piece_counts_for_its_own_side_and_type() {
+        let engine = Engine::new(MIN_HASH_MB);
+        // the kings stand on squares that swap under the tables' 63 - square flip, so they cancel
+        let kings = Board::from_fen("3k4/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(kings.piece_at_sq(SQ(4)).type_of(), PieceType::K);
+        assert_eq!(kings.piece_at_sq(SQ(59)).player(), Some(Player::Black));
+        for game_stage in 0..2 {
+            assert_eq!(material_and_tables(&engine, &kings, game_stage), (0, 0));
+        }
+
+        let types = [PieceType::P, PieceType::N, PieceType::B, PieceType::R, PieceType::Q];
+        for (letter, piece_type) in "pnbrq".chars().zip(types) {
+            let value = engine.piece_values[piece_type as usize];
+            // a white piece on d4 and a black one on e5
+            let white = Board::from_fen(&format!("3k4/8/8/8/3{}4/8/8/4K3 w - - 0 1", letter.to_ascii_uppercase())).unwrap();
+            let black = Board::from_fen(&format!("3k4/8/8/4{}3/8/8/8/4K3 w - - 0 1", letter)).unwrap();
+            assert_eq!(white.piece_at_sq(SQ(27)).type_of(), piece_type);
+            assert_eq!(black.piece_at_sq(SQ(36)).player(), Some(Player::Black));
+
+            for game_stage in 0..2 {
+                let (white_material, white_tables) = material_and_tables(&engine, &white, game_stage);
+                let (black_material, black_tables) = material_and_tables(&engine, &black, game_stage);
+                assert_eq!(white_material, value, "{}", letter);
+                assert_eq!(black_material, -value, "{}", letter);
+                assert_eq!(white_tables, engine.piece_tables[game_stage as usize][piece_type as usize][36], "{}", letter);
+                assert_eq!(black_tables, -white_tables, "{}", letter);
+            }
+        }
+    }
+
+    #[test]
+    fn wdl_favours_a_win_at_plus_three_and_a_draw_at_zero() {
+        let (win, draw, loss) = wdl(300);
+        assert!(win > 900 && loss < 10, "{} {} {}", win, draw, loss);
+        let (win, draw, loss) = wdl(0);
+        assert!(draw > 600 && win == loss, "{} {} {}", win, draw, loss);
+        let (win, draw, loss) = wdl(300);
+        assert_eq!(wdl(-300), (loss, draw, win));
+
+        let mut engine = Engine::new(MIN_HASH_MB);
+        assert_eq!(wdl_text(&engine, 300), "");
+        com(&"setoption name UCI_ShowWDL value true".to_string(), &mut engine);
+        let (win, draw, loss) = wdl(300);
+        assert_eq!(wdl_text(&engine, 300), format!(" wdl {} {} {}", win, draw, loss));
+    }
+
+    #[test]
+    fn an_unknown_option_is_accepted_quietly() {
+        let mut engine = Engine::new(MIN_HASH_MB);
+        let before = option_values(&engine);
+        com(&"setoption name SyzygyPath value /tmp/tb".to_string(), &mut engine);
+        com(&"setoption name Clear Hash".to_string(), &mut engine);
+        assert_eq!(option_values(&engine), before);
+        assert!(!before.iter().any(|(name, _)| *name == "SyzygyPath"));
+
+        // known options still apply afterwards
+        com(&"setoption name MultiPV value 3".to_string(), &mut engine);
+        assert_eq!(engine.multipv, 3);
+    }
+
+    #[test]
+    fn the_search_stops_recursing_at_max_search_ply() {
+        // the knights shuffle back to kiwipete every four plies, so the line can be made as long as needed
+        let mut engine = engine_at(TEST_FENS[1]);
+        let mut board = engine.board.clone();
+        let shuffle = ["c3b1", "b6c8", "b1c3", "c8b6"];
+        for ply in 0..MAX_SEARCH_PLY as usize {
+            assert!(!engine.past_max_plies(&board));
+            apply_uci_moves(&mut board, &[shuffle[ply % 4]]);
+        }
+        assert!(engine.past_max_plies(&board));
+
+        // at the cap quiescence stands pat without looking at a single capture
+        engine.reset_nodes();
+        quiescence(&mut engine, &mut board, MINIMUM_EVAL, MAXIMUM_EVAL);
+        assert_eq!(engine.total_nodes(), 1);
+
+        // a checking and capturing fight with every extension on, on a stack the size of the main thread's
+        let searched = thread::Builder::new().stack_size(8 << 20).spawn(|| {
+            let mut engine = engine_at("3qk3/8/8/8/8/8/8/3QK2R w - - 0 1");
+            com(&"setoption name CheckExtension value true".to_string(), &mut engine);
+            com(&"setoption name CaptureExtension value all".to_string(), &mut engine);
+            engine.max_nodes = 300_000;
+            search_to_depth(&mut engine, 60)
+        }).unwrap().join().unwrap();
+        assert!(searched != BitMove::null());
+    }
+
+    #[test]
+    fn a_go_typo_is_reported() {
+        fn words(text:&str) -> Vec<&str> {
+            text.split(' ').collect()
+        }
+        assert_eq!(unknown_go_parameters(&words("go deptg 10")), vec!["deptg", "10"]);
+        assert!(unknown_go_parameters(&words("go depth 10 searchmoves e2e4 d2d4")).is_empty());
+        assert!(unknown_go_parameters(&words("go wtime 1000 btime 1000 infinite")).is_empty());
+        assert_eq!(unknown_go_parameters(&words("go ponder movetime 50 fast")), vec!["fast"]);
+    }
+
+    #[test]
+    fn consecutive_searches_reuse_the_pool_threads() {
+        let mut engine = engine_at(TEST_FENS[1]);
+        // not through `setoption`, which clamps to the CPUs of the machine running the tests
+        engine.change_threads(2);
+        let pool = Arc::clone(&engine.pool);
+        assert_eq!(pool.thread_ids.len(), 1);
+
+        search_to_depth(&mut engine, 4);
+        search_to_depth(&mut engine, 4);
+        // setting the same count again keeps the pool too
+        engine.change_threads(2);
+        assert!(Arc::ptr_eq(&pool, &engine.pool));
+
+        // and a job still runs on the thread the pool started with
+        let (sender, ran_on) = mpsc::channel();
+        let jobs = vec![Box::new(move || sender.send(thread::current().id()).unwrap()) as Job];
+        let count = engine.pool.start(jobs);
+        engine.pool.wait(count);
+        assert_eq!(ran_on.recv().unwrap(), pool.thread_ids[0]);
+    }
+
+    #[test]
+    fn the_futility_margin_decides_a_borderline_node() {
+        let mut engine = engine_at(TEST_FENS[0]);
+        let board = engine.board.clone();
+        let stand_pat = evaluate(&mut engine, &board);
+        // 250 above beta at depth one: within the default 300, outside 200
+        let beta = stand_pat - 250;
+        assert!(!futile(&mut engine, &board, 1, beta - 1, beta));
+        com(&"setoption name FutilityMargin value 200".to_string(), &mut engine);
+        assert!(futile(&mut engine, &board, 1, beta - 1, beta));
+        // the margin grows with the square of the depth
+        assert!(!futile(&mut engine, &board, 2, beta - 1, beta));
+    }
+
+    #[test]
+    fn the_eval_cache_hits_on_transpositions() {
+        // quiet development moves in any order reach the same positions
+        let mut engine = engine_at("r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 4 4");
+        search_to_depth(&mut engine, 5);
+        let (hits, probes) = (engine.eval_cache_hits, engine.eval_cache_probes);
+        assert!(probes > 1000);
+        assert!(hits * 5 > probes, "{} hits of {} probes", hits, probes);
+
+        // the same position evaluated twice is a hit the second time
+        let board = engine.board.clone();
+        let score = evaluate(&mut engine, &board);
+        let hits = engine.eval_cache_hits;
+        assert_eq!(evaluate(&mut engine, &board), score);
+        assert_eq!(engine.eval_cache_hits, hits + 1);
+    }
+
+    #[test]
+    fn each_pair_of_limits_stops_at_the_tighter() {
+        let go = |command:&str| {
+            let mut engine = engine_at(TEST_FENS[1]);
+            // time counted in nodes, 10 to the millisecond, so every limit is a node count
+            engine.nodes_time = 10;
+            engine.search_stopped.store(false, Ordering::Relaxed);
+            com(&command.to_string(), &mut engine);
+            engine.total_nodes()
+        };
+        let depth_three = go("go depth 3");
+        assert!(depth_three < 20_000);
+
+        // depth and time
+        assert_eq!(go("go depth 3 movetime 100000000"), depth_three);
+        assert!((20_000..20_100).contains(&go("go depth 30 movetime 2000")));
+        // depth and nodes
+        assert_eq!(go("go depth 3 nodes 100000000"), depth_three);
+        assert!((20_000..20_100).contains(&go("go depth 30 nodes 20000")));
+        // nodes and time
+        assert!((20_000..20_100).contains(&go("go nodes 20000 movetime 100000000")));
+        assert!((20_000..20_100).contains(&go("go nodes 100000000 movetime 2000")));
+        // a clock doesn't override an explicit depth
+        assert_eq!(go("go depth 3 wtime 100000000 btime 100000000"), depth_three);
+
+        // depths outside 1..MAX_DEPTH_LIMIT are clamped, not read as 0
+        assert_eq!(go("go depth 0 movetime 100000000"), go("go depth 1"));
+        assert!((20_000..20_100).contains(&go("go depth 300 nodes 20000")));
+        assert!((20_000..20_100).contains(&go("go depth 300 movetime 2000")));
+    }
+
+    #[test]
+    fn futility_leaves_the_pawn_breakthrough_alone_in_a_pawn_ending() {
+        // b6! is the only winning move, a quiet one nine plies away from a queen
+        let breakthrough = |min_phase:i32| {
+            let mut engine = engine_at("7k/ppp5/8/PPP5/8/8/8/7K w - - 0 1");
+            com(&format!("setoption name FutilityMinPhase value {}", min_phase), &mut engine);
+            com(&"setoption name FutilityMargin value 1".to_string(), &mut engine);
+            let board = engine.board.clone();
+            let beta = evaluate(&mut engine, &board) - 2;
+            (futile(&mut engine, &board, 1, beta - 1, beta), search_to_depth(&mut engine, 12))
+        };
+
+        // with the phase gate off even a one centipawn margin prunes, and b6 is missed
+        let (pruned, best_move) = breakthrough(0);
+        assert!(pruned);
+        assert!(best_move.stringify() != "b5b6");
+
+        let (pruned, best_move) = breakthrough(DEFAULT_FUTILITY_MIN_PHASE);
+        assert!(!pruned);
+        assert_eq!(best_move.stringify(), "b5b6");
+    }
+
+    #[test]
+    fn each_kind_of_draw_is_adjudicated() {
+        let reason = |board:&Board| draw_reason(board, &game_history(board));
+
+        let mut board = Board::start_pos();
+        apply_uci_moves(&mut board, &["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1"]);
+        assert_eq!(reason(&board), None);
+        // the search's root claim finds the move the adjudication calls a draw
+        let claim = threefold_move(&board, &board.generate_moves().vec()).unwrap();
+        assert_eq!(claim.to_string(), "f6g8");
+        apply_uci_moves(&mut board, &["f6g8"]);
+        assert_eq!(reason(&board), Some("threefold repetition"));
+
+        let fifty = Board::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 100 80").unwrap();
+        assert_eq!(reason(&fifty), Some("fifty-move rule"));
+        let insufficient = Board::from_fen("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1").unwrap();
+        assert_eq!(reason(&insufficient), Some("insufficient material"));
+        let stalemate = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(reason(&stalemate), Some("stalemate"));
+        for board in [&board, &fifty, &insufficient, &stalemate] {
+            assert!(adjudicate_draw(board, &game_history(board)));
+        }
+
+        // a mate on the hundredth half move is a win, not a draw
+        let mate = Board::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 100 80").unwrap();
+        assert!(mate.checkmate());
+        assert!(!adjudicate_draw(&mate, &game_history(&mate)));
+        assert!(!adjudicate_draw(&Board::start_pos(), &[]));
+    }
+
+    #[test]
+    fn quit_ends_a_limited_search_at_once() {
+        let (reader, mut writer) = io::pipe().unwrap();
+        let mut engine = engine_at(TEST_FENS[1]);
+        let input = spawn_input_thread(&engine, BufReader::new(reader), io::sink());
+
+        for go in ["go depth 60", "go movetime 8000"] {
+            writeln!(writer, "{}", go).unwrap();
+            let go = input.recv().unwrap();
+            let running = thread::spawn(move || {
+                let start = Instant::now();
+                com(&go, &mut engine);
+                (engine, start.elapsed())
+            });
+            thread::sleep(Duration::from_millis(300));
+            assert!(!running.is_finished());
+
+            writeln!(writer, "quit").unwrap();
+            let (answered, elapsed) = running.join().unwrap();
+            assert!(elapsed < Duration::from_secs(3), "{:?}", elapsed);
+            assert!(!answered.pv.is_empty());
+            // the main loop still gets the `quit` once the search returned
+            assert_eq!(input.recv().unwrap().trim(), "quit");
+            engine = answered;
+        }
+    }
+
+    #[test]
+    fn go_depth_out_of_range_is_clamped() {
+        let mut engine = engine_at(TEST_FENS[0]);
+        engine.search_stopped.store(false, Ordering::Relaxed);
+        com(&"go depth 0".to_string(), &mut engine);
+        assert_eq!(engine.depth, 1);
+        // searched, not the fallback's unsearched move
+        assert!(!engine.pv_lines.is_empty());
+        assert_eq!(engine.pv_lines[0].0, engine.pv[0]);
+
+        // 300 doesn't fit a u8 and used to wrap to 0
+        engine.search_stopped.store(false, Ordering::Relaxed);
+        com(&"go depth 300 nodes 5000".to_string(), &mut engine);
+        assert_eq!(engine.depth, MAX_DEPTH_LIMIT);
+        assert!(!engine.pv_lines.is_empty());
+    }
+}